@@ -0,0 +1,40 @@
+use std::time::{Duration, Instant};
+
+use rlisp::interpreter::{Interpreter, Engine};
+
+// Recursive programs in the style of example_1, run with both engines
+const PROGRAMS: &[(&str, &str, &str)] = &[
+    ("fib", "(fn fib n (if (< n 2) 1 (+ (fib (- n 1)) (fib (- n 2)))))", "(fib 20)"),
+    ("fib_series",
+        "(fn fib n (if (< n 2) 1 (+ (fib (- n 1)) (fib (- n 2)))))
+         (define fib_series (lambda a b (if (= a b) '() (cons (fib a) (fib_series (+ a 1) b)))))",
+        "(fib_series 0 18)"),
+    ("ackermann",
+        "(fn ack m n (if (= m 0) (+ n 1) (if (= n 0) (ack (- m 1) 1) (ack (- m 1) (ack m (- n 1))))))",
+        "(ack 3 5)"),
+    ("loop", "(define sum 0)", "(dotimes (i 20000) (define sum (+ sum i)))"),
+];
+
+const RUNS: u32 = 5;
+
+// Best time of several runs of the expression, after evaluating the setup
+fn bench(engine: Engine, setup: &str, exp: &str) -> Duration {
+    let mut interp = Interpreter::new();
+    interp.set_engine(engine);
+    interp.eval_str(setup).unwrap();
+
+    (0..RUNS).map(|_| {
+        let start = Instant::now();
+        interp.eval_str(exp).unwrap();
+        start.elapsed()
+    }).min().unwrap()
+}
+
+fn main() {
+    println!("{:<12} {:>12} {:>12}", "program", "tree", "vm");
+    for (name, setup, exp) in PROGRAMS {
+        let tree = bench(Engine::Tree, setup, exp);
+        let vm = bench(Engine::Vm, setup, exp);
+        println!("{:<12} {:>12.2?} {:>12.2?}", name, tree, vm);
+    }
+}
@@ -0,0 +1,542 @@
+use std::rc::Rc;
+
+use crate::exp::LispExp;
+use crate::eval::{LispEval, quote_exp, expand_macro, loop_header, binding_name};
+use crate::interpreter::Interpreter;
+use crate::symbol::Symbol;
+use crate::vm::{Op, Param, Function};
+
+use crate::utils::exceptions::EvalError;
+
+// Compiles an expression evaluated at the top level into bytecode for the
+// virtual machine. Variables bound by procedures and loops are resolved to
+// slots of their frame, other symbols are looked up in the global environment
+// when run. Macros are expanded while compiling.
+pub(crate) fn compile(exp: &LispExp, interp: &mut Interpreter) -> Rc<Function> {
+    let mut compiler = Compiler {
+        interp,
+        builders: vec![Builder::new(true)],
+    };
+    compiler.exp(exp);
+    compiler.emit(Op::Return);
+    Rc::new(compiler.builders.pop().unwrap().function)
+}
+
+// A function being compiled, with the slots of the parameters and of the
+// variables defined in the procedure or bound by its loops
+struct Builder {
+    function: Function,
+    locals: Vec<(Symbol, u32)>,
+    top: bool,
+}
+
+impl Builder {
+
+    fn new(top: bool) -> Self {
+        Builder {
+            function: Function::default(),
+            locals: Vec::new(),
+            top,
+        }
+    }
+
+    fn lookup(&self, name: Symbol) -> Option<u32> {
+        self.locals.iter()
+            .find(|(local, _)| *local == name)
+            .map(|(_, slot)| *slot)
+    }
+
+    fn new_slot(&mut self, name: Option<Symbol>) -> u32 {
+        self.function.slots.push(name);
+        (self.function.slots.len() - 1) as u32
+    }
+
+    // A slot not bound to a variable
+    fn hidden_slot(&mut self) -> u32 {
+        self.new_slot(None)
+    }
+
+    // The slot of a variable of the procedure, added if missing
+    fn procedure_slot(&mut self, name: Symbol) -> u32 {
+        match self.lookup(name) {
+            Some(slot) => slot,
+            None => {
+                let slot = self.new_slot(Some(name));
+                self.locals.push((name, slot));
+                slot
+            }
+        }
+    }
+}
+
+// A variable bound by a loop, with the slot keeping its value from before
+// the loop
+enum LoopVar {
+    Local { slot: u32, saved: u32 },
+    Global { name: Symbol, saved: u32 },
+}
+
+struct Compiler<'a> {
+    interp: &'a mut Interpreter,
+    builders: Vec<Builder>,
+}
+
+// Names defined by a procedure body, outside of the procedures it creates.
+// They are bound before the body runs, so procedures defined in the body can
+// refer to each other.
+fn defined_names(exp: &LispExp, names: &mut Vec<Symbol>) {
+    if let LispExp::List(items) = exp {
+        match items.split_first() {
+            Some((LispExp::Symbol(Symbol::QUOTE | Symbol::LAMBDA | Symbol::DEFMACRO), _)) => {},
+            Some((LispExp::Symbol(head @ (Symbol::DEFINE | Symbol::FN)), rest)) => {
+                if let Some(name) = rest.first() {
+                    names.push(binding_name(name));
+                }
+                if *head == Symbol::DEFINE {
+                    rest.iter().skip(1).for_each(|exp| defined_names(exp, names));
+                }
+            },
+            _ => items.iter().for_each(|exp| defined_names(exp, names)),
+        }
+    }
+}
+
+impl<'a> Compiler<'a> {
+
+    fn builder(&mut self) -> &mut Builder {
+        self.builders.last_mut().unwrap()
+    }
+
+    fn function(&mut self) -> &mut Function {
+        &mut self.builder().function
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        let code = &mut self.function().code;
+        code.push(op);
+        code.len() - 1
+    }
+
+    fn here(&mut self) -> u32 {
+        self.function().code.len() as u32
+    }
+
+    // Makes the jump at the index go to the next instruction
+    fn patch(&mut self, at: usize) {
+        let target = self.here();
+        match &mut self.function().code[at] {
+            Op::Jump(t) | Op::If(t) | Op::Condition(t, _) | Op::Next(t) | Op::Below { exit: t, .. } => *t = target,
+            op => unreachable!("{:?} is not a jump", op)
+        }
+    }
+
+    fn constant(&mut self, value: LispEval) {
+        let constants = &mut self.function().constants;
+        constants.push(value);
+        let index = constants.len() - 1;
+        self.emit(Op::Const(index as u32));
+    }
+
+    // Errors in the form of expressions are raised when they are evaluated,
+    // like the tree-walker does
+    fn raise(&mut self, error: EvalError) {
+        let errors = &mut self.function().errors;
+        errors.push(error);
+        let index = errors.len() - 1;
+        self.emit(Op::Raise(index as u32));
+    }
+
+    // Leaves the expression to the tree-walking evaluator
+    fn delegate(&mut self, exp: LispExp) {
+        let exps = &mut self.function().exps;
+        exps.push(exp);
+        let index = exps.len() - 1;
+        self.emit(Op::Eval(index as u32));
+    }
+
+    // Finds a local variable, returns how many procedures up it is bound and
+    // its slot
+    fn resolve(&self, name: Symbol) -> Option<(u32, u32)> {
+        self.builders.iter().rev().enumerate().find_map(
+            |(depth, builder)| builder.lookup(name).map(|slot| (depth as u32, slot))
+        )
+    }
+
+    fn exp(&mut self, exp: &LispExp) {
+        match exp {
+            LispExp::Bool(b) => self.constant(LispEval::Bool(*b)),
+            LispExp::Number(n) => self.constant(LispEval::Number(*n)),
+            LispExp::Str(s) => self.constant(LispEval::Str(s.clone())),
+            LispExp::Symbol(s) if s.is_keyword() => self.constant(LispEval::Symbol(*s)),
+            LispExp::Symbol(s) => {
+                match self.resolve(*s) {
+                    Some((depth, slot)) => self.emit(Op::Local { depth, slot, name: *s }),
+                    None => self.emit(Op::Global(*s)),
+                };
+            },
+            LispExp::List(list) => self.list(list),
+            // Resolved expressions are compiled like the ones they come from
+            LispExp::Local(local) => self.exp(&LispExp::Symbol(local.name)),
+            LispExp::Procedure(procedure) => self.procedure(&procedure.params, &procedure.body),
+        }
+    }
+
+    fn list(&mut self, list: &[LispExp]) {
+        match list.split_first() {
+            // A list in head position is evaluated before the rest of the list
+            Some((LispExp::List(first), tail)) => {
+                self.list(first);
+                self.emit(Op::Pop);
+                self.list(tail);
+            },
+            Some((LispExp::Symbol(head), args)) => self.form(*head, args),
+            Some((LispExp::Local(local), args)) => self.call(local.name, args),
+            _ => self.raise(EvalError::InvalidListExpression()),
+        }
+    }
+
+    fn form(&mut self, head: Symbol, args: &[LispExp]) {
+        match head {
+            Symbol::QUOTE => match args {
+                [exp] => self.constant(quote_exp(exp)),
+                _ => self.raise(EvalError::InvalidNumberOfArguments()),
+            },
+            Symbol::LOAD | Symbol::MODULE | Symbol::IMPORT | Symbol::DEFMACRO => {
+                let mut exp = vec![LispExp::Symbol(head)];
+                exp.extend_from_slice(args);
+                self.delegate(LispExp::from(exp));
+            },
+            Symbol::IF => self.if_statement(args),
+            Symbol::BEGIN => self.body(args),
+            Symbol::DEFINE => self.define_variable(args),
+            Symbol::LAMBDA => self.define_lambda(args),
+            Symbol::FN => self.define_function(args),
+            Symbol::WHILE => self.while_loop(args),
+            Symbol::DO => self.do_loop(args),
+            Symbol::DOTIMES => self.dotimes_loop(args),
+            Symbol::DOLIST => self.dolist_loop(args),
+            _ => self.call(head, args),
+        }
+    }
+
+    fn call(&mut self, head: Symbol, args: &[LispExp]) {
+        match self.resolve(head) {
+            Some((depth, slot)) => {
+                self.emit(Op::LocalCallee { depth, slot, name: head });
+            },
+            None => {
+                if let Some(LispEval::Macro(macro_fn)) = self.interp.env.get(head).cloned() {
+                    match expand_macro(&macro_fn, args, self.interp) {
+                        Ok(expansion) => self.exp(&expansion),
+                        Err(e) => self.raise(e),
+                    }
+                    return;
+                }
+                self.emit(Op::GlobalCallee(head));
+            }
+        }
+        for arg in args {
+            self.exp(arg);
+        }
+        self.emit(Op::Call(args.len() as u32));
+    }
+
+    // Evaluates expressions in sequence, leaving the value of the last one
+    fn body(&mut self, body: &[LispExp]) {
+        match body.split_last() {
+            Some((last, init)) => {
+                for exp in init {
+                    self.exp(exp);
+                    self.emit(Op::Pop);
+                }
+                self.exp(last);
+            },
+            None => {
+                self.emit(Op::Void);
+            }
+        }
+    }
+
+    // Stores the value on the stack in a variable of the current procedure,
+    // or in a global at the top level
+    fn assign(&mut self, name: Symbol) {
+        let builder = self.builder();
+        let slot = match builder.lookup(name) {
+            Some(slot) => slot,
+            None if builder.top => {
+                self.emit(Op::DefineGlobal(name));
+                return;
+            },
+            None => builder.procedure_slot(name),
+        };
+        self.emit(Op::SetLocal(slot));
+    }
+
+    fn if_statement(&mut self, args: &[LispExp]) {
+        if args.len() != 3 {
+            return self.raise(EvalError::InvalidNumberOfArguments());
+        }
+        self.exp(&args[0]);
+        let otherwise = self.emit(Op::If(0));
+        self.exp(&args[1]);
+        let end = self.emit(Op::Jump(0));
+        self.patch(otherwise);
+        self.exp(&args[2]);
+        self.patch(end);
+    }
+
+    fn define_variable(&mut self, args: &[LispExp]) {
+        if args.len() != 2 {
+            return self.raise(EvalError::InvalidNumberOfArguments());
+        }
+        self.exp(&args[1]);
+        self.assign(binding_name(&args[0]));
+        self.emit(Op::Void);
+    }
+
+    fn define_function(&mut self, args: &[LispExp]) {
+        if args.len() < 3 {
+            return self.raise(EvalError::InvalidNumberOfArguments());
+        }
+        self.procedure(&args[1 .. args.len()-1], args.last().unwrap());
+        self.assign(binding_name(&args[0]));
+        self.emit(Op::Void);
+    }
+
+    fn define_lambda(&mut self, args: &[LispExp]) {
+        if args.len() < 2 {
+            return self.raise(EvalError::InvalidNumberOfArguments());
+        }
+        self.procedure(&args[0 .. args.len()-1], args.last().unwrap());
+    }
+
+    // Compiles a procedure and emits the creation of its closure
+    fn procedure(&mut self, params: &[LispExp], body: &LispExp) {
+        let mut builder = Builder::new(false);
+
+        let mut names = params.iter();
+        while let Some(param) = names.next() {
+            let param = match param {
+                LispExp::Symbol(Symbol::REST) => {
+                    Param::Rest(names.next().map(|name| builder.procedure_slot(binding_name(name))))
+                },
+                _ => Param::Slot(builder.procedure_slot(binding_name(param))),
+            };
+            builder.function.params.push(param);
+        }
+
+        let mut defined = Vec::new();
+        defined_names(body, &mut defined);
+        for name in defined {
+            builder.procedure_slot(name);
+        }
+
+        self.builders.push(builder);
+        self.exp(body);
+        self.emit(Op::Return);
+        let function = self.builders.pop().unwrap().function;
+
+        let functions = &mut self.function().functions;
+        functions.push(Rc::new(function));
+        let index = functions.len() - 1;
+        self.emit(Op::Closure(index as u32));
+    }
+
+    // (while condition body...)
+    fn while_loop(&mut self, args: &[LispExp]) {
+        let (condition, body) = match args.split_first() {
+            Some(split) => split,
+            None => return self.raise(EvalError::InvalidNumberOfArguments()),
+        };
+        let start = self.here();
+        self.exp(condition);
+        let exit = self.emit(Op::Condition(0, false));
+        for exp in body {
+            self.exp(exp);
+            self.emit(Op::Pop);
+        }
+        self.emit(Op::Loop(start));
+        self.patch(exit);
+        self.emit(Op::Void);
+    }
+
+    // Binds the variables of a loop. In procedures they are variables of the
+    // frame, at the top level they are globals. Their previous values are
+    // restored after the loop, like the tree-walker does.
+    fn bind_loop_vars(&mut self, vars: &[Symbol]) -> Vec<LoopVar> {
+        let top = self.builders.len() == 1;
+        vars.iter().map(|&name| {
+            let saved = self.builder().hidden_slot();
+            if top {
+                self.emit(Op::SaveGlobal { name, slot: saved });
+                LoopVar::Global { name, saved }
+            } else {
+                let slot = self.builder().procedure_slot(name);
+                self.emit(Op::CopyLocal { from: slot, to: saved });
+                LoopVar::Local { slot, saved }
+            }
+        }).collect()
+    }
+
+    // Pops the value on the stack into a loop variable
+    fn set_loop_var(&mut self, var: &LoopVar) {
+        match *var {
+            LoopVar::Local { slot, .. } => self.emit(Op::SetLocal(slot)),
+            LoopVar::Global { name, .. } => self.emit(Op::DefineGlobal(name)),
+        };
+    }
+
+    // Removes the binding of a loop variable
+    fn unset_loop_var(&mut self, var: &LoopVar) {
+        match *var {
+            LoopVar::Local { slot, .. } => self.emit(Op::Unset(slot)),
+            LoopVar::Global { name, .. } => self.emit(Op::UndefineGlobal(name)),
+        };
+    }
+
+    fn unbind_loop_vars(&mut self, vars: &[LoopVar]) {
+        for var in vars {
+            match *var {
+                LoopVar::Local { slot, saved } => self.emit(Op::CopyLocal { from: saved, to: slot }),
+                LoopVar::Global { name, saved } => self.emit(Op::RestoreGlobal { name, slot: saved }),
+            };
+        }
+    }
+
+    // (do ((var init step)...) (test result...) body...)
+    fn do_loop(&mut self, args: &[LispExp]) {
+        if args.len() < 2 {
+            return self.raise(EvalError::InvalidNumberOfArguments());
+        }
+        let specs = match &args[0] {
+            LispExp::List(specs) => specs,
+            _ => return self.raise(EvalError::InvalidLoopStatement()),
+        };
+        let (test, result) = match &args[1] {
+            LispExp::List(clause) if !clause.is_empty() => (&clause[0], &clause[1..]),
+            _ => return self.raise(EvalError::InvalidLoopStatement()),
+        };
+
+        // The initial values are evaluated before any variable is bound
+        let mut names = Vec::new();
+        let mut steps = Vec::new();
+        for spec in specs.iter() {
+            let (var, rest) = match loop_header(spec) {
+                Ok(header) => header,
+                Err(e) => return self.raise(e),
+            };
+            match rest {
+                [_] => steps.push(None),
+                [_, step] => steps.push(Some(step)),
+                _ => return self.raise(EvalError::InvalidLoopStatement()),
+            }
+            self.exp(&rest[0]);
+            names.push(var);
+        }
+
+        let vars = self.bind_loop_vars(&names);
+        for var in vars.iter().rev() {
+            self.set_loop_var(var);
+        }
+
+        let start = self.here();
+        self.exp(test);
+        let done = self.emit(Op::Condition(0, true));
+        for exp in &args[2..] {
+            self.exp(exp);
+            self.emit(Op::Pop);
+        }
+        // All steps are evaluated before any variable is updated
+        let mut updated = Vec::new();
+        for (var, step) in vars.iter().zip(&steps) {
+            if let Some(step) = step {
+                self.exp(step);
+                updated.push(var);
+            }
+        }
+        for var in updated.into_iter().rev() {
+            self.set_loop_var(var);
+        }
+        self.emit(Op::Loop(start));
+
+        self.patch(done);
+        self.body(result);
+        self.unbind_loop_vars(&vars);
+    }
+
+    // (dotimes (var count [result]) body...)
+    fn dotimes_loop(&mut self, args: &[LispExp]) {
+        let (header, body) = match args.split_first() {
+            Some(split) => split,
+            None => return self.raise(EvalError::InvalidNumberOfArguments()),
+        };
+        let (var, rest) = match loop_header(header) {
+            Ok(header) => header,
+            Err(e) => return self.raise(e),
+        };
+        if rest.is_empty() || rest.len() > 2 {
+            return self.raise(EvalError::InvalidLoopStatement());
+        }
+
+        self.exp(&rest[0]);
+        self.emit(Op::CheckNumber);
+        let limit = self.builder().hidden_slot();
+        self.emit(Op::SetLocal(limit));
+        let counter = self.builder().hidden_slot();
+        self.constant(LispEval::Number(0.0));
+        self.emit(Op::SetLocal(counter));
+
+        let vars = self.bind_loop_vars(std::slice::from_ref(&var));
+
+        let start = self.here();
+        let exit = self.emit(Op::Below { counter, limit, exit: 0 });
+        self.emit(Op::Local { depth: 0, slot: counter, name: var });
+        self.set_loop_var(&vars[0]);
+        for exp in body {
+            self.exp(exp);
+            self.emit(Op::Pop);
+        }
+        self.emit(Op::Increment(counter));
+        self.emit(Op::Loop(start));
+
+        self.patch(exit);
+        self.emit(Op::Local { depth: 0, slot: counter, name: var });
+        self.set_loop_var(&vars[0]);
+        self.body(&rest[1..]);
+        self.unbind_loop_vars(&vars);
+    }
+
+    // (dolist (var sequence [result]) body...)
+    fn dolist_loop(&mut self, args: &[LispExp]) {
+        let (header, body) = match args.split_first() {
+            Some(split) => split,
+            None => return self.raise(EvalError::InvalidNumberOfArguments()),
+        };
+        let (var, rest) = match loop_header(header) {
+            Ok(header) => header,
+            Err(e) => return self.raise(e),
+        };
+        if rest.is_empty() || rest.len() > 2 {
+            return self.raise(EvalError::InvalidLoopStatement());
+        }
+
+        self.exp(&rest[0]);
+        self.emit(Op::ToList);
+
+        let vars = self.bind_loop_vars(std::slice::from_ref(&var));
+        let start = self.here();
+        let exit = self.emit(Op::Next(0));
+        self.set_loop_var(&vars[0]);
+        for exp in body {
+            self.exp(exp);
+            self.emit(Op::Pop);
+        }
+        self.emit(Op::Loop(start));
+        self.patch(exit);
+
+        // The variable is no longer bound in the result
+        self.unset_loop_var(&vars[0]);
+        self.body(&rest[1..]);
+        self.unbind_loop_vars(&vars);
+    }
+}
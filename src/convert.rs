@@ -0,0 +1,321 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::rc::Rc;
+
+use crate::list::List;
+use crate::eval::LispEval;
+
+use crate::utils::exceptions::EvalError;
+
+// Conversion of Rust values into rlisp values
+pub trait IntoLisp {
+    fn into_lisp(self) -> LispEval;
+}
+
+// Conversion of rlisp values back into Rust values
+pub trait FromLisp: Sized {
+    fn from_lisp(value: LispEval) -> Result<Self, EvalError>;
+}
+
+// Argument lists for calling rlisp procedures from Rust
+pub trait IntoLispArgs {
+    fn into_lisp_args(self) -> Vec<LispEval>;
+}
+
+fn conversion_error(expected: &str, value: &LispEval) -> EvalError {
+    EvalError::ConversionError(format!("expected {}, got {}", expected, value))
+}
+
+
+impl IntoLisp for LispEval {
+    fn into_lisp(self) -> LispEval {
+        self
+    }
+}
+
+impl FromLisp for LispEval {
+    fn from_lisp(value: LispEval) -> Result<Self, EvalError> {
+        Ok(value)
+    }
+}
+
+// The unit type converts to void and accepts any value, so results can be ignored
+impl IntoLisp for () {
+    fn into_lisp(self) -> LispEval {
+        LispEval::Void
+    }
+}
+
+impl FromLisp for () {
+    fn from_lisp(_: LispEval) -> Result<Self, EvalError> {
+        Ok(())
+    }
+}
+
+impl IntoLisp for bool {
+    fn into_lisp(self) -> LispEval {
+        LispEval::Bool(self)
+    }
+}
+
+impl FromLisp for bool {
+    fn from_lisp(value: LispEval) -> Result<Self, EvalError> {
+        match value {
+            LispEval::Bool(b) => Ok(b),
+            _ => Err(conversion_error("bool", &value))
+        }
+    }
+}
+
+macro_rules! impl_float {
+    ($($t:ty),*) => {
+        $(
+            impl IntoLisp for $t {
+                fn into_lisp(self) -> LispEval {
+                    LispEval::Number(self as f64)
+                }
+            }
+
+            impl FromLisp for $t {
+                fn from_lisp(value: LispEval) -> Result<Self, EvalError> {
+                    match value {
+                        LispEval::Number(n) => Ok(n as $t),
+                        _ => Err(conversion_error(stringify!($t), &value))
+                    }
+                }
+            }
+        )*
+    };
+}
+
+// Integers only accept whole numbers within their range
+macro_rules! impl_integer {
+    ($($t:ty),*) => {
+        $(
+            impl IntoLisp for $t {
+                fn into_lisp(self) -> LispEval {
+                    LispEval::Number(self as f64)
+                }
+            }
+
+            impl FromLisp for $t {
+                fn from_lisp(value: LispEval) -> Result<Self, EvalError> {
+                    match value {
+                        LispEval::Number(n) if n.fract() == 0.0
+                            && n >= <$t>::MIN as f64
+                            && n <= <$t>::MAX as f64 => Ok(n as $t),
+                        _ => Err(conversion_error(stringify!($t), &value))
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_float!(f64, f32);
+impl_integer!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+impl IntoLisp for String {
+    fn into_lisp(self) -> LispEval {
+        LispEval::Str(self)
+    }
+}
+
+impl IntoLisp for &str {
+    fn into_lisp(self) -> LispEval {
+        LispEval::Str(self.to_string())
+    }
+}
+
+impl FromLisp for String {
+    fn from_lisp(value: LispEval) -> Result<Self, EvalError> {
+        match value {
+            LispEval::Str(s) => Ok(s),
+            _ => Err(conversion_error("string", &value))
+        }
+    }
+}
+
+// None is represented by the empty list '(), void is also read as None
+impl<T: IntoLisp> IntoLisp for Option<T> {
+    fn into_lisp(self) -> LispEval {
+        match self {
+            Some(value) => value.into_lisp(),
+            None => LispEval::List(List::new()),
+        }
+    }
+}
+
+impl<T: FromLisp> FromLisp for Option<T> {
+    fn from_lisp(value: LispEval) -> Result<Self, EvalError> {
+        match value {
+            LispEval::Void => Ok(None),
+            LispEval::List(list) if list.is_empty() => Ok(None),
+            _ => Ok(Some(T::from_lisp(value)?))
+        }
+    }
+}
+
+impl<T: IntoLisp> IntoLisp for Vec<T> {
+    fn into_lisp(self) -> LispEval {
+        LispEval::List(self.into_iter().map(IntoLisp::into_lisp).collect())
+    }
+}
+
+impl<T: FromLisp> FromLisp for Vec<T> {
+    fn from_lisp(value: LispEval) -> Result<Self, EvalError> {
+        match value {
+            LispEval::List(list) => list.into_iter().map(T::from_lisp).collect(),
+            LispEval::Vector(vector) => vector.borrow().iter().cloned().map(T::from_lisp).collect(),
+            LispEval::Set(set) => Rc::unwrap_or_clone(set).into_iter().map(T::from_lisp).collect(),
+            _ => Err(conversion_error("list", &value))
+        }
+    }
+}
+
+impl<T: IntoLisp> IntoLisp for HashSet<T> {
+    fn into_lisp(self) -> LispEval {
+        LispEval::Set(Rc::new(self.into_iter().map(IntoLisp::into_lisp).collect()))
+    }
+}
+
+impl<T: FromLisp + Eq + Hash> FromLisp for HashSet<T> {
+    fn from_lisp(value: LispEval) -> Result<Self, EvalError> {
+        match value {
+            LispEval::Set(set) => Rc::unwrap_or_clone(set).into_iter().map(T::from_lisp).collect(),
+            LispEval::List(list) => list.into_iter().map(T::from_lisp).collect(),
+            _ => Err(conversion_error("set", &value))
+        }
+    }
+}
+
+// Association lists of (key value) pairs are accepted in place of maps
+impl<K: IntoLisp, V: IntoLisp> IntoLisp for HashMap<K, V> {
+    fn into_lisp(self) -> LispEval {
+        LispEval::Map(Rc::new(self.into_iter().map(|(k, v)| (k.into_lisp(), v.into_lisp())).collect()))
+    }
+}
+
+impl<K: FromLisp + Eq + Hash, V: FromLisp> FromLisp for HashMap<K, V> {
+    fn from_lisp(value: LispEval) -> Result<Self, EvalError> {
+        match value {
+            LispEval::Map(map) => Rc::unwrap_or_clone(map).into_iter().map(|(k, v)| {
+                Ok((K::from_lisp(k)?, V::from_lisp(v)?))
+            }).collect(),
+            LispEval::List(list) => list.into_iter().map(<(K, V)>::from_lisp).collect(),
+            _ => Err(conversion_error("map", &value))
+        }
+    }
+}
+
+macro_rules! impl_tuple {
+    ($len:expr, $($name:ident),+) => {
+        impl<$($name: IntoLisp),+> IntoLisp for ($($name,)+) {
+            fn into_lisp(self) -> LispEval {
+                LispEval::List(List::from(self.into_lisp_args()))
+            }
+        }
+
+        impl<$($name: IntoLisp),+> IntoLispArgs for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn into_lisp_args(self) -> Vec<LispEval> {
+                let ($($name,)+) = self;
+                vec![$($name.into_lisp()),+]
+            }
+        }
+
+        impl<$($name: FromLisp),+> FromLisp for ($($name,)+) {
+            fn from_lisp(value: LispEval) -> Result<Self, EvalError> {
+                match value {
+                    LispEval::List(list) if list.len() == $len => {
+                        let mut items = list.into_iter();
+                        Ok(($($name::from_lisp(items.next().unwrap())?,)+))
+                    },
+                    _ => Err(conversion_error(concat!("list of ", $len, " items"), &value))
+                }
+            }
+        }
+    };
+}
+
+impl_tuple!(1, A);
+impl_tuple!(2, A, B);
+impl_tuple!(3, A, B, C);
+impl_tuple!(4, A, B, C, D);
+impl_tuple!(5, A, B, C, D, E);
+impl_tuple!(6, A, B, C, D, E, F);
+
+impl IntoLispArgs for () {
+    fn into_lisp_args(self) -> Vec<LispEval> {
+        vec![]
+    }
+}
+
+impl IntoLispArgs for Vec<LispEval> {
+    fn into_lisp_args(self) -> Vec<LispEval> {
+        self
+    }
+}
+
+impl IntoLispArgs for &[LispEval] {
+    fn into_lisp_args(self) -> Vec<LispEval> {
+        self.to_vec()
+    }
+}
+
+impl<const N: usize> IntoLispArgs for &[LispEval; N] {
+    fn into_lisp_args(self) -> Vec<LispEval> {
+        self.to_vec()
+    }
+}
+
+
+// Helpers used by `impl_lisp_record!`. Records are represented as maps
+// from field name strings to values.
+pub fn record_into_lisp(fields: Vec<(&str, LispEval)>) -> LispEval {
+    LispEval::Map(Rc::new(fields.into_iter().map(|(name, value)| {
+        (LispEval::Str(name.to_string()), value)
+    }).collect()))
+}
+
+pub fn record_fields(value: LispEval) -> Result<HashMap<String, LispEval>, EvalError> {
+    HashMap::<String, LispEval>::from_lisp(value)
+}
+
+pub fn take_field<T: FromLisp>(fields: &mut HashMap<String, LispEval>, name: &str
+) -> Result<T, EvalError> {
+    match fields.remove(name) {
+        Some(value) => T::from_lisp(value),
+        None => T::from_lisp(LispEval::List(List::new())).map_err(|_| {
+            EvalError::ConversionError(format!("missing field {}", name))
+        })
+    }
+}
+
+// Implements IntoLisp and FromLisp for a struct with named fields, whose
+// field types implement the conversion traits themselves.
+//
+//     struct Point { x: f64, y: f64 }
+//     impl_lisp_record!(Point { x, y });
+#[macro_export]
+macro_rules! impl_lisp_record {
+    ($name:ident { $($field:ident),* $(,)? }) => {
+        impl $crate::convert::IntoLisp for $name {
+            fn into_lisp(self) -> $crate::eval::LispEval {
+                $crate::convert::record_into_lisp(vec![
+                    $((stringify!($field), $crate::convert::IntoLisp::into_lisp(self.$field))),*
+                ])
+            }
+        }
+
+        impl $crate::convert::FromLisp for $name {
+            fn from_lisp(value: $crate::eval::LispEval
+            ) -> Result<Self, $crate::utils::exceptions::EvalError> {
+                let mut fields = $crate::convert::record_fields(value)?;
+                Ok($name {
+                    $($field: $crate::convert::take_field(&mut fields, stringify!($field))?),*
+                })
+            }
+        }
+    };
+}
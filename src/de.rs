@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::forward_to_deserialize_any;
+
+use crate::list::List;
+use crate::eval::LispEval;
+use crate::interpreter::Interpreter;
+
+use crate::utils::exceptions::SerdeError;
+
+// Deserializes Rust values from evaluated rlisp values. Lists, vectors and sets
+// map to sequences, maps (or association lists of (key value) pairs) to maps
+// and structs.
+pub struct Deserializer {
+    value: LispEval,
+}
+
+impl Deserializer {
+    pub fn new(value: LispEval) -> Self {
+        Deserializer {
+            value
+        }
+    }
+}
+
+pub fn from_value<T: DeserializeOwned>(value: LispEval) -> Result<T, SerdeError> {
+    T::deserialize(Deserializer::new(value))
+}
+
+// Evaluates the source with the default environment and deserializes the
+// value of its last expression
+pub fn from_str<T: DeserializeOwned>(source: &str) -> Result<T, SerdeError> {
+    let value = Interpreter::new().eval_str(source)?;
+    from_value(value)
+}
+
+pub fn from_file<T: DeserializeOwned, P: AsRef<Path>>(path: P) -> Result<T, SerdeError> {
+    let value = Interpreter::new().eval_file(path)?;
+    from_value(value)
+}
+
+fn unexpected(expected: &str, value: &LispEval) -> SerdeError {
+    SerdeError::Message(format!("expected {}, got {}", expected, value))
+}
+
+// Both '() and void stand for a missing value
+fn is_nothing(value: &LispEval) -> bool {
+    matches!(value, LispEval::Void) || matches!(value, LispEval::List(list) if list.is_empty())
+}
+
+// Splits a (key value) pair of an association list
+fn into_pair(item: LispEval) -> Result<(LispEval, LispEval), SerdeError> {
+    match item {
+        LispEval::List(pair) if pair.len() == 2 => {
+            let mut pair = pair.into_iter();
+            Ok((pair.next().unwrap(), pair.next().unwrap()))
+        },
+        _ => Err(unexpected("(key value) pair", &item))
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+    type Error = SerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.value {
+            LispEval::Void => visitor.visit_unit(),
+            LispEval::Bool(b) => visitor.visit_bool(b),
+            LispEval::Number(n) => {
+                if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
+                    visitor.visit_i64(n as i64)
+                } else {
+                    visitor.visit_f64(n)
+                }
+            },
+            LispEval::Str(s) => visitor.visit_string(s),
+            LispEval::Symbol(s) => visitor.visit_str(s.name()),
+            LispEval::List(list) => visitor.visit_seq(SeqDeserializer::new(list.into_iter().collect())),
+            LispEval::Vector(vector) => visitor.visit_seq(SeqDeserializer::new(vector.borrow().clone())),
+            LispEval::Set(set) => visitor.visit_seq(SeqDeserializer::new(set.iter().cloned().collect())),
+            LispEval::Map(map) => visitor.visit_map(MapDeserializer::from_map(Rc::unwrap_or_clone(map))),
+            value => Err(unexpected("data", &value))
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        if is_nothing(&self.value) {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        if is_nothing(&self.value) {
+            visitor.visit_unit()
+        } else {
+            Err(unexpected("'()", &self.value))
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V
+    ) -> Result<V::Value, SerdeError> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V
+    ) -> Result<V::Value, SerdeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.value {
+            LispEval::Map(map) => visitor.visit_map(MapDeserializer::from_map(Rc::unwrap_or_clone(map))),
+            LispEval::List(list) => visitor.visit_map(MapDeserializer::new(list.into_iter().collect())),
+            value => Err(unexpected("map", &value))
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V
+    ) -> Result<V::Value, SerdeError> {
+        self.deserialize_map(visitor)
+    }
+
+    // Unit variants are written as strings, other variants as a map with a
+    // single "Variant" key
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V
+    ) -> Result<V::Value, SerdeError> {
+        match self.value {
+            LispEval::Str(s) => visitor.visit_enum(s.into_deserializer()),
+            LispEval::Map(map) if map.len() == 1 => {
+                let (variant, value) = Rc::unwrap_or_clone(map).into_iter().next().unwrap();
+                visitor.visit_enum(EnumDeserializer { variant, value })
+            },
+            LispEval::List(list) if list.len() == 1 => {
+                let (variant, value) = into_pair(list.into_iter().next().unwrap())?;
+                visitor.visit_enum(EnumDeserializer { variant, value })
+            },
+            value => Err(unexpected("enum variant", &value))
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf seq tuple tuple_struct identifier ignored_any
+    }
+}
+
+struct SeqDeserializer {
+    iter: std::vec::IntoIter<LispEval>,
+}
+
+impl SeqDeserializer {
+    fn new(items: Vec<LispEval>) -> Self {
+        SeqDeserializer {
+            iter: items.into_iter()
+        }
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for SeqDeserializer {
+    type Error = SerdeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T
+    ) -> Result<Option<T::Value>, SerdeError> {
+        match self.iter.next() {
+            Some(value) => seed.deserialize(Deserializer::new(value)).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct MapDeserializer {
+    iter: std::vec::IntoIter<LispEval>,
+    value: Option<LispEval>,
+}
+
+impl MapDeserializer {
+    fn new(items: Vec<LispEval>) -> Self {
+        MapDeserializer {
+            iter: items.into_iter(),
+            value: None,
+        }
+    }
+
+    fn from_map(map: HashMap<LispEval, LispEval>) -> Self {
+        MapDeserializer::new(map.into_iter().map(|(key, value)| {
+            LispEval::List(List::from(vec![key, value]))
+        }).collect())
+    }
+}
+
+impl<'de> de::MapAccess<'de> for MapDeserializer {
+    type Error = SerdeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K
+    ) -> Result<Option<K::Value>, SerdeError> {
+        match self.iter.next() {
+            Some(item) => {
+                let (key, value) = into_pair(item)?;
+                self.value = Some(value);
+                seed.deserialize(Deserializer::new(key)).map(Some)
+            },
+            None => Ok(None)
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V
+    ) -> Result<V::Value, SerdeError> {
+        let value = self.value.take().ok_or(
+            SerdeError::Message("value is missing".to_string())
+        )?;
+        seed.deserialize(Deserializer::new(value))
+    }
+}
+
+struct EnumDeserializer {
+    variant: LispEval,
+    value: LispEval,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumDeserializer {
+    type Error = SerdeError;
+    type Variant = Deserializer;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V
+    ) -> Result<(V::Value, Deserializer), SerdeError> {
+        let variant = seed.deserialize(Deserializer::new(self.variant))?;
+        Ok((variant, Deserializer::new(self.value)))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Deserializer {
+    type Error = SerdeError;
+
+    fn unit_variant(self) -> Result<(), SerdeError> {
+        de::Deserializer::deserialize_unit(self, de::IgnoredAny).map(|_| ())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T
+    ) -> Result<T::Value, SerdeError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V
+    ) -> Result<V::Value, SerdeError> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], visitor: V
+    ) -> Result<V::Value, SerdeError> {
+        de::Deserializer::deserialize_map(self, visitor)
+    }
+}
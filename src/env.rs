@@ -1,131 +1,427 @@
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::cell::RefCell;
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
 
 // use crate::exp::LispExp;
-use crate::eval::LispEval;
+use crate::eval::{LispEval, sequence_items};
+use crate::interpreter::Interpreter;
+use crate::native::{NativeFn, Arity};
+use crate::list::{List, Pair};
+use crate::symbol::{Symbol, SymbolMap};
 
 use crate::utils::exceptions::EvalError;
+use crate::limits::Resource;
 
-#[derive(Clone)]
+// Global bindings, keyed on interned symbols. Names given as strings are
+// interned first.
+#[derive(Clone, Default)]
 pub struct LispEnv {
-    pub set: HashMap<String, LispEval>
+    pub set: SymbolMap<LispEval>
 }
 
 impl LispEnv {
 
     pub fn new() -> Self {
         LispEnv {
-            set: HashMap::new()
+            set: SymbolMap::default()
         }
     }
 
-    pub fn get(&self, symbol: &str) -> Option<&LispEval> {
-        self.set.get(symbol)
+    pub fn get(&self, symbol: impl Into<Symbol>) -> Option<&LispEval> {
+        self.set.get(&symbol.into())
     }
 
-    pub fn add(&mut self, symbol: &str, exp: LispEval) {
-        self.set.insert(symbol.to_string(), exp);
+    pub fn add(&mut self, symbol: impl Into<Symbol>, exp: LispEval) {
+        self.set.insert(symbol.into(), exp);
     }
 
+    pub fn remove(&mut self, symbol: impl Into<Symbol>) {
+        self.set.remove(&symbol.into());
+    }
+
+    // Registers a native function under its own name
+    pub fn add_native(&mut self, func: NativeFn) {
+        let name = Symbol::intern(&func.name);
+        self.add(name, LispEval::Func(Rc::new(func)));
+    }
+
+}
+
+// Local bindings of a procedure call. Lambdas keep the scope they were created
+// in, so frames are shared and chained to the enclosing frame. Names missing
+// from the chain are looked up in the global environment.
+//
+// Variables of the procedure are kept in slots, found by index by resolved
+// expressions. Other variables, defined by macro expansions, are kept by name.
+pub struct Scope {
+    names: Rc<[Symbol]>,
+    slots: RefCell<Vec<Option<LispEval>>>,
+    vars: RefCell<SymbolMap<LispEval>>,
+    parent: Option<Rc<Scope>>,
 }
 
+impl Scope {
+
+    pub fn new(names: Rc<[Symbol]>, parent: Option<Rc<Scope>>) -> Self {
+        Scope {
+            slots: RefCell::new(vec![None; names.len()]),
+            names,
+            vars: RefCell::new(SymbolMap::default()),
+            parent
+        }
+    }
+
+    fn position(&self, symbol: Symbol) -> Option<usize> {
+        self.names.iter().position(|name| *name == symbol)
+    }
+
+    pub fn get(&self, symbol: Symbol) -> Option<LispEval> {
+        let mut scope = self;
+        loop {
+            if let Some(value) = scope.get_local(symbol) {
+                return Some(value);
+            }
+            scope = scope.parent.as_deref()?;
+        }
+    }
+
+    // Looks the symbol up in this frame only
+    pub fn get_local(&self, symbol: Symbol) -> Option<LispEval> {
+        match self.position(symbol) {
+            Some(slot) => self.slots.borrow()[slot].clone(),
+            None => self.vars.borrow().get(&symbol).cloned(),
+        }
+    }
+
+    pub fn add(&self, symbol: Symbol, exp: LispEval) {
+        match self.position(symbol) {
+            Some(slot) => self.slots.borrow_mut()[slot] = Some(exp),
+            None => {
+                self.vars.borrow_mut().insert(symbol, exp);
+            },
+        }
+    }
+
+    pub fn remove(&self, symbol: Symbol) {
+        match self.position(symbol) {
+            Some(slot) => self.slots.borrow_mut()[slot] = None,
+            None => {
+                self.vars.borrow_mut().remove(&symbol);
+            },
+        }
+    }
+
+    // The slot of the frame `depth` levels up
+    pub(crate) fn slot(&self, depth: u32, slot: u32) -> Option<LispEval> {
+        let mut scope = self;
+        for _ in 0..depth {
+            scope = scope.parent.as_deref().expect("locals are resolved to enclosing frames");
+        }
+        scope.slots.borrow()[slot as usize].clone()
+    }
+
+    // Looks the symbol up in the frames enclosing the one `depth` levels up
+    pub(crate) fn outer(&self, depth: u32, symbol: Symbol) -> Option<LispEval> {
+        let mut scope = self;
+        for _ in 0..depth {
+            scope = scope.parent.as_deref().expect("locals are resolved to enclosing frames");
+        }
+        scope.parent.as_deref()?.get(symbol)
+    }
+
+    pub(crate) fn set_slot(&self, slot: u32, exp: LispEval) {
+        self.slots.borrow_mut()[slot as usize] = Some(exp);
+    }
+}
+
+// Definitions written in rlisp, evaluated on top of the builtins
+const PRELUDE: &str = include_str!("prelude.rl");
+
+type TypePredicate = fn(&LispEval) -> bool;
+
+// The builtins and the prelude
 pub fn default_env() -> LispEnv {
+    let mut interp = Interpreter::with_env(bare_env());
+    if let Err(e) = interp.eval_str(PRELUDE) {
+        panic!("invalid prelude: {:?}", e);
+    }
+    interp.into_env()
+}
+
+// The builtins only, without the prelude
+pub fn bare_env() -> LispEnv {
     let mut env = LispEnv::new();
 
-    env.add("print", LispEval::Func(print));
+    env.add_native(NativeFn::new("print", Arity::Exact(1),
+        "Prints a value.", print));
+    env.add_native(NativeFn::new("void", Arity::Exact(0),
+        "Returns the void value, the result of forms evaluated for their effects.", void));
+    env.add_native(NativeFn::new("void?", Arity::Exact(1),
+        "Checks if a value is void.", is_void));
+    env.add_native(NativeFn::new("null?", Arity::Exact(1),
+        "Checks if a value is the empty list '().", is_null));
 
     // Logical operators
-    env.add("not", LispEval::Func(not));
-    env.add("and", LispEval::Func(and));
-    env.add("or", LispEval::Func(or));
+    env.add_native(NativeFn::new("not", Arity::Exact(1),
+        "Negates a boolean.", not));
+    env.add_native(NativeFn::new("and", Arity::AtLeast(1),
+        "Logical conjunction of booleans.", and));
+    env.add_native(NativeFn::new("or", Arity::AtLeast(1),
+        "Logical disjunction of booleans.", or));
 
     // Basic math functions
-    env.add("+", LispEval::Func(add));
-    env.add("-", LispEval::Func(sub));
-    env.add("*", LispEval::Func(mul));
-    env.add("/", LispEval::Func(dev));
-    env.add("%", LispEval::Func(modulus));
+    env.add_native(NativeFn::new("+", Arity::AtLeast(0),
+        "Adds numbers, 0 without any.", add));
+    env.add_native(NativeFn::new("-", Arity::AtLeast(1),
+        "Subtracts the rest of the numbers from the first one.", sub));
+    env.add_native(NativeFn::new("*", Arity::AtLeast(0),
+        "Multiplies numbers, 1 without any.", mul));
+    env.add_native(NativeFn::new("/", Arity::AtLeast(1),
+        "Divides the first number by the rest of the numbers.", dev));
+    env.add_native(NativeFn::new("%", Arity::AtLeast(1),
+        "Remainder of dividing the first number by the rest.", modulus));
 
     // Basic comparison relations
-    env.add("=", LispEval::Func(equals));
-    env.add("<", LispEval::Func(less_than));
-    env.add(">", LispEval::Func(more_than));
-    env.add("<=", LispEval::Func(less_or_equal));
-    env.add(">=", LispEval::Func(more_or_equal));
+    env.add_native(NativeFn::new("=", Arity::AtLeast(2),
+        "Compares numbers for equality.", equals));
+    env.add_native(NativeFn::new("<", Arity::AtLeast(2),
+        "Less-than comparison of numbers.", less_than));
+    env.add_native(NativeFn::new(">", Arity::AtLeast(2),
+        "Greater-than comparison of numbers.", more_than));
+    env.add_native(NativeFn::new("<=", Arity::AtLeast(2),
+        "Less-or-equal comparison of numbers.", less_or_equal));
+    env.add_native(NativeFn::new(">=", Arity::AtLeast(2),
+        "Greater-or-equal comparison of numbers.", more_or_equal));
+
+    // Type predicates
+    env.add_native(NativeFn::new("type-of", Arity::Exact(1),
+        "Returns the type of a value as a symbol, like number or list.", type_of));
+    let predicates: [(&str, &str, TypePredicate); 11] = [
+        ("bool?", "Checks if a value is a boolean.", |x| matches!(x, LispEval::Bool(_))),
+        ("number?", "Checks if a value is a number.", |x| matches!(x, LispEval::Number(_))),
+        ("integer?", "Checks if a value is a whole number.",
+            |x| matches!(x, LispEval::Number(n) if n.fract() == 0.0)),
+        ("string?", "Checks if a value is a string.", |x| matches!(x, LispEval::Str(_))),
+        ("list?", "Checks if a value is a proper list.",
+            |x| matches!(x, LispEval::List(list) if list.is_proper())),
+        ("pair?", "Checks if a value is a non-empty list or a pair.",
+            |x| matches!(x, LispEval::List(list) if !list.is_empty())),
+        ("vector?", "Checks if a value is a vector.", |x| matches!(x, LispEval::Vector(_))),
+        ("set?", "Checks if a value is a set.", |x| matches!(x, LispEval::Set(_))),
+        ("map?", "Checks if a value is a map.", |x| matches!(x, LispEval::Map(_))),
+        ("procedure?", "Checks if a value can be called, a builtin or a lambda.",
+            |x| matches!(x, LispEval::Func(_) | LispEval::Lambda(_))),
+        ("lambda?", "Checks if a value is a lambda.", |x| matches!(x, LispEval::Lambda(_))),
+    ];
+    for (name, doc, pred) in predicates {
+        env.add_native(NativeFn::new(name, Arity::Exact(1), doc,
+            move |_, args| Ok(LispEval::Bool(pred(&args[0])))));
+    }
 
     // Math constants
     env.add("pi", LispEval::Number(core::f64::consts::PI));
     env.add("e",  LispEval::Number(core::f64::consts::E));
 
+    // Symbol operations
+    env.add_native(NativeFn::new("eq?", Arity::AtLeast(2),
+        "Checks if values are equal, comparing collections by their items.", eq));
+    env.add_native(NativeFn::new("symbol?", Arity::Exact(1),
+        "Checks if a value is a symbol other than a keyword.", is_symbol));
+    env.add_native(NativeFn::new("keyword?", Arity::Exact(1),
+        "Checks if a value is a keyword like :name.", is_keyword));
+    env.add_native(NativeFn::new("symbol->string", Arity::Exact(1),
+        "Returns the name of a symbol or keyword.", symbol_to_string));
+    env.add_native(NativeFn::new("string->symbol", Arity::Exact(1),
+        "Returns the symbol with the given name.", string_to_symbol));
+    env.add_native(NativeFn::new("gensym", Arity::Range(0, 1),
+        "Returns a new unique symbol, named with an optional prefix.", gensym));
+
     // List operations
-    env.add("list", LispEval::Func(to_list));
-    env.add("head", LispEval::Func(head));
-    env.add("cons", LispEval::Func(cons));
+    env.add_native(NativeFn::new("list", Arity::AtLeast(0),
+        "Creates a list from the arguments.", to_list));
+    env.add_native(NativeFn::new("head", Arity::Exact(1),
+        "Returns the first item of a list.", head));
+    env.add_native(NativeFn::new("car", Arity::Exact(1),
+        "Returns the first item of a list.", head));
+    env.add_native(NativeFn::new("tail", Arity::Exact(1),
+        "Returns a list without its first item.", tail));
+    env.add_native(NativeFn::new("cdr", Arity::Exact(1),
+        "Returns a list without its first item.", tail));
+    env.add_native(NativeFn::new("cons", Arity::Exact(2),
+        "Prepends an item to a list, or makes a pair when the tail is not a list.", cons));
+    for name in ["caar", "cadr", "cdar", "cddr",
+                 "caaar", "caadr", "cadar", "caddr", "cdaar", "cdadr", "cddar", "cdddr"] {
+        env.add_native(NativeFn::new(name, Arity::Exact(1),
+            "Composition of car and cdr, applied from the last letter to the first.",
+            move |_, args| car_cdr_path(name, &args[0])));
+    }
+    env.add_native(NativeFn::new("length", Arity::Exact(1),
+        "Returns the number of items of a list, vector, set, map or string.", length));
+    env.add_native(NativeFn::new("append", Arity::AtLeast(0),
+        "Concatenates lists, sharing the last one.", append));
+    env.add_native(NativeFn::new("reverse", Arity::Exact(1),
+        "Returns the items of a list in reverse order.", reverse));
+    env.add_native(NativeFn::new("nth", Arity::Exact(2),
+        "Returns the item of a list at a zero-based index.", nth));
+    env.add_native(NativeFn::new("last", Arity::Exact(1),
+        "Returns the last item of a list.", last));
+    env.add_native(NativeFn::new("take", Arity::Exact(2),
+        "Returns the first n items of a list.", take));
+    env.add_native(NativeFn::new("drop", Arity::Exact(2),
+        "Returns a list without its first n items.", drop));
+    env.add_native(NativeFn::new("range", Arity::Range(1, 3),
+        "Returns the numbers from start (0) up to end, exclusive, by step (1).", range));
+    env.add_native(NativeFn::new("zip", Arity::AtLeast(1),
+        "Groups the items of lists at the same positions into lists.", zip));
+    env.add_native(NativeFn::new("flatten", Arity::Exact(1),
+        "Returns the non-list items of nested lists in a single list.", flatten));
+    env.add_native(NativeFn::new("member", Arity::Exact(2),
+        "Returns the tail of a list starting with an item, or '() when missing.", member));
+    env.add_native(NativeFn::new("list-index", Arity::Exact(2),
+        "Returns the index of the first item of a list matching a predicate, or '().", list_index));
+
+    // Vector operations
+    env.add_native(NativeFn::new("vector", Arity::AtLeast(0),
+        "Creates a vector from the arguments.", to_vector));
+    env.add_native(NativeFn::new("vector-ref", Arity::Exact(2),
+        "Returns the item of a vector at a zero-based index.", vector_ref));
+    env.add_native(NativeFn::new("vector-set!", Arity::Exact(3),
+        "Replaces the item of a vector at an index, returns the vector. A vector cannot hold itself.", vector_set));
+    env.add_native(NativeFn::new("vector-length", Arity::Exact(1),
+        "Returns the number of items of a vector.", vector_length));
+    env.add_native(NativeFn::new("vector-push!", Arity::AtLeast(2),
+        "Appends items to the end of a vector, returns the vector. A vector cannot hold itself.", vector_push));
+    env.add_native(NativeFn::new("vector-slice", Arity::Range(2, 3),
+        "Returns a new vector with the items from start up to end (exclusive).", vector_slice));
+    env.add_native(NativeFn::new("vector->list", Arity::Exact(1),
+        "Returns the items of a vector as a list.", vector_to_list));
+    env.add_native(NativeFn::new("list->vector", Arity::Exact(1),
+        "Returns the items of a list as a new vector.", list_to_vector));
 
     // Set operations
-    env.add("set", LispEval::Func(to_set));
-    env.add("union", LispEval::Func(union));
-    env.add("inter", LispEval::Func(intersection));
+    env.add_native(NativeFn::new("set", Arity::AtLeast(0),
+        "Creates a set from the arguments.", to_set));
+    env.add_native(NativeFn::new("union", Arity::AtLeast(1),
+        "Union of sets.", union));
+    env.add_native(NativeFn::new("inter", Arity::AtLeast(1),
+        "Intersection of sets.", intersection));
+
+    // Map operations
+    env.add_native(NativeFn::new("hash-map", Arity::AtLeast(0),
+        "Creates a map from alternating keys and values.", to_map));
+    env.add_native(NativeFn::new("get", Arity::Range(2, 3),
+        "Returns the value of a key in a map, or the default (or '()) when missing.", get));
+    env.add_native(NativeFn::new("assoc", Arity::AtLeast(2),
+        "With a map first, returns the map with the given keys set to the values. \
+        Otherwise, with a key and an association list, returns the first pair with that key, or '().", assoc));
+    env.add_native(NativeFn::new("dissoc", Arity::AtLeast(1),
+        "Returns a map without the given keys.", dissoc));
+    env.add_native(NativeFn::new("keys", Arity::Exact(1),
+        "Returns the keys of a map as a list.", keys));
+    env.add_native(NativeFn::new("values", Arity::Exact(1),
+        "Returns the values of a map as a list.", values));
+    env.add_native(NativeFn::new("contains-key?", Arity::Exact(2),
+        "Checks if a map contains a key.", contains_key));
+    env.add_native(NativeFn::new("merge", Arity::AtLeast(1),
+        "Merges maps, later maps take precedence.", merge));
+    env.add_native(NativeFn::new("map->list", Arity::Exact(1),
+        "Returns the entries of a map as a list of (key value) lists.", map_to_list));
+
+    // File operations, only available after Interpreter::allow_fs
+    env.add_native(NativeFn::new("read-file", Arity::Exact(1),
+        "Returns the contents of a file as a string.", read_file));
+    env.add_native(NativeFn::new("read-lines", Arity::Exact(1),
+        "Returns the lines of a file as a list of strings.", read_lines));
+    env.add_native(NativeFn::new("write-file", Arity::Exact(2),
+        "Writes a string to a file, replacing its contents.", write_file));
+    env.add_native(NativeFn::new("append-file", Arity::Exact(2),
+        "Appends a string to the end of a file.", append_file));
+    env.add_native(NativeFn::new("file-exists?", Arity::Exact(1),
+        "Checks if a file or directory exists.", file_exists));
+    env.add_native(NativeFn::new("list-dir", Arity::Exact(1),
+        "Returns the sorted names of the entries of a directory.", list_dir));
+
+    // Higher-order functions
+    env.add_native(NativeFn::new("apply", Arity::AtLeast(2),
+        "Calls a procedure with the arguments followed by the items of the last one, a list.", apply));
+    env.add_native(NativeFn::new("for-each", Arity::AtLeast(2),
+        "Calls a procedure on the items of the sequences.", for_each));
+    env.add_native(NativeFn::new("map", Arity::AtLeast(2),
+        "Collects the results of a procedure applied to the items of the sequences.", map));
+    env.add_native(NativeFn::new("filter", Arity::Exact(2),
+        "Keeps the items of a sequence matching a predicate.", filter));
+    env.add_native(NativeFn::new("reduce", Arity::Exact(2),
+        "Combines the items of a non-empty sequence with a procedure.", reduce));
+    env.add_native(NativeFn::new("fold-left", Arity::AtLeast(3),
+        "Folds the sequences from the left starting with an initial value.", fold_left));
+    env.add_native(NativeFn::new("fold-right", Arity::AtLeast(3),
+        "Folds the sequences from the right starting with an initial value.", fold_right));
+    env.add_native(NativeFn::new("any", Arity::AtLeast(2),
+        "Checks if a predicate holds for some items of the sequences.", any));
+    env.add_native(NativeFn::new("every", Arity::AtLeast(2),
+        "Checks if a predicate holds for all items of the sequences.", every));
+    env.add_native(NativeFn::new("find", Arity::Exact(2),
+        "Returns the first item of a sequence matching a predicate or '().", find));
+    env.add_native(NativeFn::new("count", Arity::AtLeast(2),
+        "Counts the items of the sequences matching a predicate.", count));
 
     env
 }
 
 
-fn unary_logic_operator(args: &LispEval, op: fn(bool)->bool
+fn unary_logic_operator(args: &[LispEval], op: fn(bool)->bool
 ) -> Result<LispEval, EvalError> {
     let mut res = false;
-    if let LispEval::List(list) = args {
-        if let LispEval::Bool(b) = list[0] {
-            res = op(b)
-        }
+    if let LispEval::Bool(b) = args[0] {
+        res = op(b)
     }
     Ok(LispEval::Bool(res))
 }
 
-fn not(args: &LispEval) -> Result<LispEval, EvalError>  {
+fn not(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError>  {
     unary_logic_operator(args, |x| !x)
 }
 
-fn binary_logic_operator(args: &LispEval, op: fn(bool, bool)->bool
+fn binary_logic_operator(args: &[LispEval], op: fn(bool, bool)->bool
 ) -> Result<LispEval, EvalError> {
-    
+
     let mut res = false;
-    if let LispEval::List(list) = args {
-        for (i, arg) in list.iter().enumerate() {
-            if let LispEval::Bool(b) = arg {
-                if i == 0 {
-                    res = *b;
-                } else {
-                    res = op(res, *b);
-                }
+    for (i, arg) in args.iter().enumerate() {
+        if let LispEval::Bool(b) = arg {
+            if i == 0 {
+                res = *b;
+            } else {
+                res = op(res, *b);
             }
         }
     }
     Ok(LispEval::Bool(res))
 }
 
-fn and(args: &LispEval) -> Result<LispEval, EvalError>  {
+fn and(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError>  {
     binary_logic_operator(args, |x,y| x&&y)
 }
 
-fn or(args: &LispEval) -> Result<LispEval, EvalError>  {
+fn or(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError>  {
     binary_logic_operator(args, |x,y| x||y)
 }
 
 
 
-fn accumulate(args: &LispEval, op: fn(f64, f64)->f64
+fn accumulate(args: &[LispEval], op: fn(f64, f64)->f64
 ) -> Result<LispEval, EvalError> {
-    
+
     let mut res = 0.0;
-    if let LispEval::List(list) = args {
-        for (i, arg) in list.iter().enumerate() {
-            if let LispEval::Number(n) = arg {
-                if i == 0 {
-                    res = *n;
-                } else {
-                    res = op(res, *n);
-                }
+    for (i, arg) in args.iter().enumerate() {
+        if let LispEval::Number(n) = arg {
+            if i == 0 {
+                res = *n;
+            } else {
+                res = op(res, *n);
             }
         }
     }
@@ -133,161 +429,827 @@ fn accumulate(args: &LispEval, op: fn(f64, f64)->f64
 }
 
 
-fn add(args: &LispEval) -> Result<LispEval, EvalError>  {
+fn add(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError>  {
     accumulate(args, |x,y| x+y)
 }
 
-fn sub(args: &LispEval) -> Result<LispEval, EvalError> {
+fn sub(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
     accumulate(args, |x,y| x-y)
 }
 
-fn mul(args: &LispEval) -> Result<LispEval, EvalError> {
+fn mul(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    if args.is_empty() {
+        return Ok(LispEval::Number(1.0));
+    }
     accumulate(args, |x,y| x*y)
 }
 
-fn dev(args: &LispEval) -> Result<LispEval, EvalError> {
+fn dev(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
     accumulate(args, |x,y| x/y)
 }
 
-fn modulus(args: &LispEval) -> Result<LispEval, EvalError> {
+fn modulus(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
     accumulate(args, |x,y| x%y)
 }
 
-fn compare(args: &LispEval, rel: fn(f64, f64)->bool
-) -> Result<LispEval, EvalError> 
+fn compare(args: &[LispEval], rel: fn(f64, f64)->bool
+) -> Result<LispEval, EvalError>
 {
     let mut prev = 0.0;
     let mut res = false;
-    if let LispEval::List(list) = args {
-        for (i, arg) in list.iter().enumerate() {
-            if let LispEval::Number(n) = arg {
-                if i > 0 {
-                    res = rel(prev, *n);
-                }
-                prev = *n;
+    for (i, arg) in args.iter().enumerate() {
+        if let LispEval::Number(n) = arg {
+            if i > 0 {
+                res = rel(prev, *n);
             }
+            prev = *n;
         }
     }
     Ok(LispEval::Bool(res))
 }
 
-fn equals(args: &LispEval) -> Result<LispEval, EvalError> {
+fn equals(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
     compare(args, |x,y| x==y)
 }
 
-fn less_than(args: &LispEval) -> Result<LispEval, EvalError> {
+fn less_than(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
     compare(args, |x,y| x<y)
 }
 
-fn more_than(args: &LispEval) -> Result<LispEval, EvalError> {
+fn more_than(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
     compare(args, |x,y| x>y)
 }
 
-fn less_or_equal(args: &LispEval) -> Result<LispEval, EvalError> {
+fn less_or_equal(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
     compare(args, |x,y| x<=y)
 }
 
-fn more_or_equal(args: &LispEval) -> Result<LispEval, EvalError> {
+fn more_or_equal(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
     compare(args, |x,y| x>=y)
 }
 
-fn to_list(args: &LispEval) -> Result<LispEval, EvalError> {
-    match args {
-        LispEval::List(_) => Ok(args.clone()),
+fn type_of(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    Ok(LispEval::Symbol(Symbol::intern(args[0].type_name())))
+}
+
+fn eq(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    Ok(LispEval::Bool(args.windows(2).all(|pair| pair[0] == pair[1])))
+}
+
+fn is_symbol(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    Ok(LispEval::Bool(matches!(&args[0], LispEval::Symbol(s) if !s.is_keyword())))
+}
+
+fn is_keyword(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    Ok(LispEval::Bool(matches!(&args[0], LispEval::Symbol(s) if s.is_keyword())))
+}
+
+fn symbol_to_string(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    match &args[0] {
+        LispEval::Symbol(s) => Ok(LispEval::Str(s.name().to_string())),
         _ => Err(EvalError::InvalidAgrumentType())
     }
 }
 
-fn head(args: &LispEval) -> Result<LispEval, EvalError> {
-    if let LispEval::List(args_vec) = args {
-       if args_vec.len() > 1 {
-           return Err(EvalError::InvalidNumberOfArguments ());
-       }
-       match &args_vec[0] {
-           LispEval::List(list) => Ok(list[0].clone()),
-           _ => Err(EvalError::InvalidAgrumentType())
-       }
+// Interned names are never freed, so new symbols count toward the memory
+// limit, with the space the interner keeps for them
+fn charge_symbol(interp: &mut Interpreter, name_len: usize) -> Result<(), EvalError> {
+    interp.charge_memory(name_len + 2 * std::mem::size_of::<&str>())
+}
+
+fn string_to_symbol(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    match &args[0] {
+        LispEval::Str(s) if !s.is_empty() => {
+            if Symbol::find(s).is_none() {
+                charge_symbol(interp, s.len())?;
+            }
+            Ok(LispEval::Symbol(Symbol::intern(s)))
+        },
+        _ => Err(EvalError::InvalidAgrumentType())
     }
-    else {
-        Err(EvalError::UnexpectedExpression())
+}
+
+fn gensym(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let prefix = match args.first() {
+        Some(LispEval::Str(s)) => s.as_str(),
+        Some(_) => return Err(EvalError::InvalidAgrumentType()),
+        None => "g__"
+    };
+    // The counter appended to the prefix has 20 digits at most
+    charge_symbol(interp, prefix.len() + 20)?;
+    Ok(LispEval::Symbol(Symbol::gensym(prefix)))
+}
+
+fn to_list(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    Ok(LispEval::List(List::from(args.to_vec())))
+}
+
+fn head(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    match &args[0] {
+        LispEval::List(list) => list.car().cloned().ok_or(
+            EvalError::EmptySequence()
+        ),
+        _ => Err(EvalError::InvalidAgrumentType())
     }
 }
 
-fn cons(args: &LispEval) -> Result<LispEval, EvalError> {
-    if let LispEval::List(args_vec) = args {
-        if args_vec.len() != 2 {
-            return Err(EvalError::InvalidNumberOfArguments ());
+fn tail(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    match &args[0] {
+        LispEval::List(list) => list.cdr().cloned().ok_or(
+            EvalError::EmptySequence()
+        ),
+        _ => Err(EvalError::InvalidAgrumentType())
+    }
+}
+
+// Shares the second argument as the tail of the new list. A tail which is
+// not a list makes a dotted pair.
+fn cons(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    Ok(LispEval::List(List::cons(args[0].clone(), args[1].clone())))
+}
+
+fn list_arg(arg: &LispEval) -> Result<&List, EvalError> {
+    match arg {
+        LispEval::List(list) if list.is_proper() => Ok(list),
+        _ => Err(EvalError::InvalidAgrumentType())
+    }
+}
+
+// Non-negative whole numbers used as list indices and counts
+fn index_arg(arg: &LispEval) -> Result<usize, EvalError> {
+    match arg {
+        LispEval::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Ok(*n as usize),
+        _ => Err(EvalError::InvalidAgrumentType())
+    }
+}
+
+// Follows a cadr-style name like "cadr" from its last letter to the first
+fn car_cdr_path(name: &str, arg: &LispEval) -> Result<LispEval, EvalError> {
+    let mut value = arg.clone();
+    for op in name[1..name.len()-1].chars().rev() {
+        let list = match &value {
+            LispEval::List(list) => list,
+            _ => return Err(EvalError::InvalidAgrumentType())
+        };
+        let next = match op {
+            'a' => list.car(),
+            _ => list.cdr(),
+        };
+        value = next.cloned().ok_or(
+            EvalError::EmptySequence()
+        )?;
+    }
+    Ok(value)
+}
+
+fn length(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let len = match &args[0] {
+        LispEval::Vector(vector) => vector.borrow().len(),
+        LispEval::Set(set) => set.len(),
+        LispEval::Map(map) => map.len(),
+        LispEval::Str(s) => s.chars().count(),
+        arg => list_arg(arg)?.len(),
+    };
+    Ok(LispEval::Number(len as f64))
+}
+
+fn append(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let (last, lists) = match args.split_last() {
+        Some(split) => split,
+        None => return Ok(LispEval::List(List::new()))
+    };
+
+    let mut count = 0;
+    for list in lists {
+        count += list_arg(list)?.len();
+    }
+    interp.reserve(count, std::mem::size_of::<Pair>())?;
+    let mut items = Vec::new();
+    for list in lists {
+        items.extend(list_arg(list)?.iter().cloned());
+    }
+    Ok(List::from_items_with_tail(items, last.clone()))
+}
+
+fn reverse(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let list = list_arg(&args[0])?;
+    interp.reserve(list.len(), std::mem::size_of::<Pair>())?;
+    let mut result = List::new();
+    for item in list {
+        result = List::cons(item.clone(), LispEval::List(result));
+    }
+    Ok(LispEval::List(result))
+}
+
+fn nth(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let index = index_arg(&args[1])?;
+    list_arg(&args[0])?.iter().nth(index).cloned().ok_or(
+        EvalError::IndexOutOfRange(index)
+    )
+}
+
+fn last(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    list_arg(&args[0])?.iter().last().cloned().ok_or(
+        EvalError::EmptySequence()
+    )
+}
+
+fn take(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let count = index_arg(&args[1])?;
+    let list = list_arg(&args[0])?;
+    if list.len() < count {
+        return Err(EvalError::IndexOutOfRange(count));
+    }
+    interp.reserve(count, std::mem::size_of::<Pair>())?;
+    Ok(LispEval::List(List::from(list.iter().take(count).cloned().collect::<Vec<_>>())))
+}
+
+fn drop(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let count = index_arg(&args[1])?;
+    let mut rest = list_arg(&args[0])?.clone();
+    for _ in 0..count {
+        rest = match rest.cdr() {
+            Some(LispEval::List(next)) => next.clone(),
+            _ => return Err(EvalError::IndexOutOfRange(count))
+        };
+    }
+    Ok(LispEval::List(rest))
+}
+
+fn range(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let mut bounds = Vec::new();
+    for arg in args {
+        match arg {
+            LispEval::Number(n) => bounds.push(*n),
+            _ => return Err(EvalError::InvalidAgrumentType())
         }
-        let mut result = Vec::new();
-        result.push(args_vec[0].clone());
+    }
+    let (start, end, step) = match bounds[..] {
+        [end] => (0.0, end, 1.0),
+        [start, end] => (start, end, 1.0),
+        [start, end, step] => (start, end, step),
+        _ => return Err(EvalError::InvalidNumberOfArguments())
+    };
+    // The step has to change the numbers from start to end
+    let finite = start.is_finite() && end.is_finite() && step.is_finite();
+    if !finite || step == 0.0 || start + step == start || end - step == end {
+        return Err(EvalError::InvalidAgrumentType());
+    }
 
-        if let LispEval::List(list) = &args_vec[1] {
-            for item in list {
-                result.push(item.clone());
+    let count = ((end - start) / step).ceil().max(0.0);
+    if count >= (isize::MAX as usize / std::mem::size_of::<LispEval>()) as f64 {
+        return Err(EvalError::ResourceExhausted(Resource::Memory));
+    }
+    interp.reserve(count as usize, std::mem::size_of::<Pair>())?;
+    let items: Vec<LispEval> = (0..count as usize)
+        .map(|i| LispEval::Number(start + i as f64 * step))
+        .collect();
+    Ok(LispEval::List(List::from(items)))
+}
+
+fn zip(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let mut iters = Vec::new();
+    let mut count = usize::MAX;
+    for arg in args {
+        let list = list_arg(arg)?;
+        count = count.min(list.len());
+        iters.push(list.iter());
+    }
+    // A group of a cell for every list and the cell holding it
+    interp.reserve(count, (args.len() + 1) * std::mem::size_of::<Pair>())?;
+
+    let mut result = Vec::new();
+    loop {
+        let mut group = Vec::new();
+        for iter in iters.iter_mut() {
+            match iter.next() {
+                Some(item) => group.push(item.clone()),
+                None => return Ok(LispEval::List(List::from(result)))
             }
         }
-        Ok(LispEval::List(result))
+        result.push(LispEval::List(List::from(group)));
     }
-    else {
-        Err(EvalError::UnexpectedExpression())
+}
+
+fn flatten(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let mut result = Vec::new();
+    let mut stack = vec![list_arg(&args[0])?.iter()];
+
+    while let Some(iter) = stack.last_mut() {
+        match iter.next() {
+            Some(LispEval::List(list)) if list.is_proper() => stack.push(list.iter()),
+            Some(LispEval::List(_)) => return Err(EvalError::InvalidAgrumentType()),
+            Some(item) => result.push(item.clone()),
+            None => {
+                stack.pop();
+            }
+        }
     }
+    Ok(LispEval::List(List::from(result)))
 }
 
-fn to_set(args: &LispEval) -> Result<LispEval, EvalError> {
-    let mut set = HashSet::new();
+fn member(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let mut rest = list_arg(&args[1])?.clone();
+    while let Some(item) = rest.car() {
+        if *item == args[0] {
+            return Ok(LispEval::List(rest));
+        }
+        rest = match rest.cdr() {
+            Some(LispEval::List(next)) => next.clone(),
+            _ => List::new()
+        };
+    }
+    Ok(LispEval::List(List::new()))
+}
 
-    if let LispEval::List(list) = args {
-        for item in list {
-            set.insert(item.clone());
+fn list_assoc(key: &LispEval, alist: &LispEval) -> Result<LispEval, EvalError> {
+    for pair in list_arg(alist)? {
+        match pair {
+            LispEval::List(entry) if entry.car() == Some(key) => return Ok(pair.clone()),
+            LispEval::List(entry) if !entry.is_empty() => {},
+            _ => return Err(EvalError::InvalidAgrumentType())
         }
-    }  
-    Ok(LispEval::Set(set))
+    }
+    Ok(LispEval::List(List::new()))
 }
 
-fn print(args: &LispEval) -> Result<LispEval, EvalError> {
-    if let LispEval::List(args_vec) = args {
-        if args_vec.len() > 1 {
-            return Err(EvalError::InvalidNumberOfArguments ());
+fn list_index(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    for (i, item) in list_arg(&args[1])?.iter().enumerate() {
+        if apply_predicate(interp, &args[0], std::slice::from_ref(item))? {
+            return Ok(LispEval::Number(i as f64));
         }
-        println!("{}", &args_vec[0]);
-        Ok(args_vec[0].clone())
-     }
-     else {
-         Err(EvalError::UnexpectedExpression())
-     }
+    }
+    Ok(LispEval::List(List::new()))
+}
+
+fn vector_value(items: Vec<LispEval>) -> LispEval {
+    LispEval::Vector(Rc::new(RefCell::new(items)))
 }
 
+fn vector_arg(arg: &LispEval) -> Result<&Rc<RefCell<Vec<LispEval>>>, EvalError> {
+    match arg {
+        LispEval::Vector(vector) => Ok(vector),
+        _ => Err(EvalError::InvalidAgrumentType())
+    }
+}
+
+fn to_vector(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    Ok(vector_value(args.to_vec()))
+}
+
+fn vector_ref(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let index = index_arg(&args[1])?;
+    vector_arg(&args[0])?.borrow().get(index).cloned().ok_or(
+        EvalError::IndexOutOfRange(index)
+    )
+}
+
+// Whether the value is the vector or holds it at any depth. Vectors are never
+// stored in themselves, printing, comparing or hashing them would not end.
+fn holds_vector(value: &LispEval, vector: &Rc<RefCell<Vec<LispEval>>>) -> bool {
+    match value {
+        LispEval::Vector(other) => {
+            Rc::ptr_eq(other, vector) || other.borrow().iter().any(|item| holds_vector(item, vector))
+        },
+        LispEval::List(list) => {
+            list.iter().any(|item| holds_vector(item, vector))
+                || list.improper_tail().is_some_and(|tail| holds_vector(tail, vector))
+        },
+        LispEval::Set(set) => set.iter().any(|item| holds_vector(item, vector)),
+        LispEval::Map(map) => {
+            map.iter().any(|(key, value)| holds_vector(key, vector) || holds_vector(value, vector))
+        },
+        _ => false
+    }
+}
+
+fn vector_set(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let index = index_arg(&args[1])?;
+    let vector = vector_arg(&args[0])?;
+    if index >= vector.borrow().len() {
+        return Err(EvalError::IndexOutOfRange(index));
+    }
+    if holds_vector(&args[2], vector) {
+        return Err(EvalError::InvalidAgrumentType());
+    }
+    interp.charge_value(&args[2])?;
+    vector.borrow_mut()[index] = args[2].clone();
+    Ok(args[0].clone())
+}
 
-fn union(args: &LispEval) -> Result<LispEval, EvalError> {
+fn vector_length(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    Ok(LispEval::Number(vector_arg(&args[0])?.borrow().len() as f64))
+}
+
+fn vector_push(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let vector = vector_arg(&args[0])?;
+    if args[1..].iter().any(|item| holds_vector(item, vector)) {
+        return Err(EvalError::InvalidAgrumentType());
+    }
+    for item in &args[1..] {
+        interp.charge_memory(std::mem::size_of::<LispEval>())?;
+        interp.charge_value(item)?;
+    }
+    vector.borrow_mut().extend(args[1..].iter().cloned());
+    Ok(args[0].clone())
+}
+
+fn vector_slice(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let vector = vector_arg(&args[0])?.borrow();
+    let start = index_arg(&args[1])?;
+    let end = match args.get(2) {
+        Some(end) => index_arg(end)?,
+        None => vector.len()
+    };
+    if end > vector.len() {
+        return Err(EvalError::IndexOutOfRange(end));
+    }
+    if start > end {
+        return Err(EvalError::IndexOutOfRange(start));
+    }
+    interp.reserve(end - start, std::mem::size_of::<LispEval>())?;
+    Ok(vector_value(vector[start..end].to_vec()))
+}
+
+fn vector_to_list(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let vector = vector_arg(&args[0])?.borrow();
+    interp.reserve(vector.len(), std::mem::size_of::<Pair>())?;
+    Ok(LispEval::List(vector.iter().cloned().collect()))
+}
+
+fn list_to_vector(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let list = list_arg(&args[0])?;
+    interp.reserve(list.len(), std::mem::size_of::<LispEval>())?;
+    Ok(vector_value(list.iter().cloned().collect()))
+}
+
+fn to_set(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let mut set = HashSet::new();
+
+    for item in args {
+        set.insert(item.clone());
+    }
+    Ok(LispEval::Set(Rc::new(set)))
+}
+
+fn print(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    match &args[0] {
+        LispEval::Str(s) => println!("{}", s),
+        value => println!("{}", value),
+    }
+    Ok(LispEval::Void)
+}
+
+fn void(_: &mut Interpreter, _: &[LispEval]) -> Result<LispEval, EvalError> {
+    Ok(LispEval::Void)
+}
+
+fn is_void(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    Ok(LispEval::Bool(matches!(&args[0], LispEval::Void)))
+}
+
+fn is_null(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    Ok(LispEval::Bool(matches!(&args[0], LispEval::List(list) if list.is_empty())))
+}
+
+
+fn union(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
     let mut result = HashSet::<LispEval>::new();
-    if let LispEval::List(args_vec) = args {
-        for item in args_vec {
-            if let LispEval::Set(set) = item {
-                result.extend(set.iter().cloned());
-            }
+    for item in args {
+        if let LispEval::Set(set) = item {
+            result.extend(set.iter().cloned());
         }
-        Ok(LispEval::Set(result))
-     }
-     else {
-         Err(EvalError::UnexpectedExpression())
-     }
+    }
+    Ok(LispEval::Set(Rc::new(result)))
 }
 
-fn intersection(args: &LispEval) -> Result<LispEval, EvalError> {
+fn intersection(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
     let mut result = HashSet::<LispEval>::new();
-    if let LispEval::List(args_vec) = args {
-        for (i, item) in args_vec.iter().enumerate() {
-            if let LispEval::Set(set) = item {
-                if i == 0 {
-                    result = set.clone();
-                } else {
-                    result = result.intersection(&set).cloned().collect();
-                }
+    for (i, item) in args.iter().enumerate() {
+        if let LispEval::Set(set) = item {
+            if i == 0 {
+                result = HashSet::clone(set);
+            } else {
+                result = result.intersection(&set).cloned().collect();
+            }
+        }
+    }
+    Ok(LispEval::Set(Rc::new(result)))
+}
+
+fn map_arg(arg: &LispEval) -> Result<&Rc<HashMap<LispEval, LispEval>>, EvalError> {
+    match arg {
+        LispEval::Map(map) => Ok(map),
+        _ => Err(EvalError::InvalidAgrumentType())
+    }
+}
+
+// Adds alternating keys and values to a map
+fn insert_pairs(map: &mut HashMap<LispEval, LispEval>, pairs: &[LispEval]
+) -> Result<(), EvalError> {
+    if !pairs.len().is_multiple_of(2) {
+        return Err(EvalError::InvalidNumberOfArguments());
+    }
+    for pair in pairs.chunks(2) {
+        map.insert(pair[0].clone(), pair[1].clone());
+    }
+    Ok(())
+}
+
+fn to_map(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let mut map = HashMap::new();
+    insert_pairs(&mut map, args)?;
+    Ok(LispEval::Map(Rc::new(map)))
+}
+
+fn get(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let map = map_arg(&args[0])?;
+    match map.get(&args[1]) {
+        Some(value) => Ok(value.clone()),
+        None => Ok(args.get(2).cloned().unwrap_or(LispEval::List(List::new())))
+    }
+}
+
+fn assoc(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    if let [key, alist] = args {
+        if !matches!(key, LispEval::Map(_)) {
+            return list_assoc(key, alist);
+        }
+    }
+    // The map is copied only when it is shared
+    let mut map = map_arg(&args[0])?.clone();
+    insert_pairs(Rc::make_mut(&mut map), &args[1..])?;
+    Ok(LispEval::Map(map))
+}
+
+fn dissoc(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let mut map = map_arg(&args[0])?.clone();
+    for key in &args[1..] {
+        Rc::make_mut(&mut map).remove(key);
+    }
+    Ok(LispEval::Map(map))
+}
+
+fn keys(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let map = map_arg(&args[0])?;
+    interp.reserve(map.len(), std::mem::size_of::<Pair>())?;
+    Ok(LispEval::List(map.keys().cloned().collect()))
+}
+
+fn values(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let map = map_arg(&args[0])?;
+    interp.reserve(map.len(), std::mem::size_of::<Pair>())?;
+    Ok(LispEval::List(map.values().cloned().collect()))
+}
+
+fn contains_key(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let map = map_arg(&args[0])?;
+    Ok(LispEval::Bool(map.contains_key(&args[1])))
+}
+
+fn merge(_: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let mut result = HashMap::new();
+    for arg in args {
+        result.extend(map_arg(arg)?.iter().map(|(k, v)| (k.clone(), v.clone())));
+    }
+    Ok(LispEval::Map(Rc::new(result)))
+}
+
+fn map_to_list(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let map = map_arg(&args[0])?;
+    // A list of the key and the value for every entry
+    interp.reserve(map.len(), 3 * std::mem::size_of::<Pair>())?;
+    Ok(LispEval::List(map.iter().map(|(key, value)| {
+        LispEval::List(List::from(vec![key.clone(), value.clone()]))
+    }).collect()))
+}
+
+// Groups the items of the sequences into argument lists for each call,
+// stopping as soon as the shortest sequence is exhausted
+fn zip_sequences(sequences: &[LispEval]) -> Result<Vec<Vec<LispEval>>, EvalError> {
+    let mut iters = Vec::new();
+    for seq in sequences {
+        iters.push(sequence_items(seq.clone())?.into_iter());
+    }
+
+    let mut calls = Vec::new();
+    loop {
+        let mut call_args = Vec::new();
+        for iter in iters.iter_mut() {
+            match iter.next() {
+                Some(item) => call_args.push(item),
+                None => return Ok(calls)
             }
         }
-        Ok(LispEval::Set(result))
-     }
-     else {
-        Err(EvalError::UnexpectedExpression())
-     }
-}
\ No newline at end of file
+        calls.push(call_args);
+    }
+}
+
+// Collects the results into a sequence of the same kind as the input,
+// sets stay sets and vectors give new vectors
+fn same_kind(seq: &LispEval, items: Vec<LispEval>) -> LispEval {
+    match seq {
+        LispEval::Set(_) => LispEval::Set(Rc::new(items.into_iter().collect())),
+        LispEval::Vector(_) => vector_value(items),
+        _ => LispEval::List(List::from(items))
+    }
+}
+
+fn apply_predicate(interp: &mut Interpreter, func: &LispEval, args: &[LispEval]
+) -> Result<bool, EvalError> {
+
+    match interp.apply(func, args)? {
+        LispEval::Bool(res) => Ok(res),
+        _ => Err(EvalError::InvalidAgrumentType())
+    }
+}
+
+fn io_error(e: std::io::Error) -> EvalError {
+    EvalError::IoError(e.to_string())
+}
+
+// Resolves a path relative to the allowed root directory, rejecting paths
+// which lead outside of it, including through symbolic links
+fn fs_path(interp: &Interpreter, arg: &LispEval) -> Result<PathBuf, EvalError> {
+    let root = interp.fs_root.as_ref().ok_or(
+        EvalError::PermissionDenied("file system access is disabled".to_string())
+    )?;
+    let path = match arg {
+        LispEval::Str(path) => Path::new(path),
+        _ => return Err(EvalError::InvalidAgrumentType())
+    };
+    let denied = || EvalError::PermissionDenied(path.display().to_string());
+    if path.components().any(|c| c == Component::ParentDir) {
+        return Err(denied());
+    }
+
+    // Files which do not exist yet are resolved through their closest existing ancestor
+    let full = root.join(path);
+    let mut existing = full.as_path();
+    while !existing.exists() {
+        existing = existing.parent().ok_or_else(denied)?;
+    }
+    let mut resolved = existing.canonicalize().map_err(io_error)?;
+    let missing = full.strip_prefix(existing).unwrap();
+    if !missing.as_os_str().is_empty() {
+        resolved.push(missing);
+    }
+
+    if resolved.starts_with(root) {
+        Ok(resolved)
+    } else {
+        Err(denied())
+    }
+}
+
+fn string_arg(arg: &LispEval) -> Result<&str, EvalError> {
+    match arg {
+        LispEval::Str(s) => Ok(s),
+        _ => Err(EvalError::InvalidAgrumentType())
+    }
+}
+
+fn read_file(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let path = fs_path(interp, &args[0])?;
+    Ok(LispEval::Str(fs::read_to_string(path).map_err(io_error)?))
+}
+
+fn read_lines(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let path = fs_path(interp, &args[0])?;
+    let contents = fs::read_to_string(path).map_err(io_error)?;
+    Ok(LispEval::List(contents.lines().map(|line| LispEval::Str(line.to_string())).collect()))
+}
+
+fn write_file(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let path = fs_path(interp, &args[0])?;
+    fs::write(path, string_arg(&args[1])?).map_err(io_error)?;
+    Ok(LispEval::Void)
+}
+
+fn append_file(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let path = fs_path(interp, &args[0])?;
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(path).map_err(io_error)?;
+    file.write_all(string_arg(&args[1])?.as_bytes()).map_err(io_error)?;
+    Ok(LispEval::Void)
+}
+
+fn file_exists(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let path = fs_path(interp, &args[0])?;
+    Ok(LispEval::Bool(path.exists()))
+}
+
+fn list_dir(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let path = fs_path(interp, &args[0])?;
+    let mut names = Vec::new();
+    for entry in fs::read_dir(path).map_err(io_error)? {
+        let name = entry.map_err(io_error)?.file_name();
+        names.push(name.to_string_lossy().into_owned());
+    }
+    names.sort();
+    Ok(LispEval::List(names.into_iter().map(LispEval::Str).collect()))
+}
+
+fn apply(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let (last, first) = args[1..].split_last().unwrap();
+    let mut call_args = first.to_vec();
+    call_args.extend(list_arg(last)?.iter().cloned());
+    interp.apply(&args[0], &call_args)
+}
+
+fn for_each(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    for call_args in zip_sequences(&args[1..])? {
+        interp.apply(&args[0], &call_args)?;
+    }
+    Ok(LispEval::Void)
+}
+
+fn map(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let mut result = Vec::new();
+    for call_args in zip_sequences(&args[1..])? {
+        result.push(interp.apply(&args[0], &call_args)?);
+    }
+
+    match &args[1..] {
+        [seq] => Ok(same_kind(seq, result)),
+        _ => Ok(LispEval::List(List::from(result)))
+    }
+}
+
+fn filter(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let mut result = Vec::new();
+    for item in sequence_items(args[1].clone())? {
+        if apply_predicate(interp, &args[0], std::slice::from_ref(&item))? {
+            result.push(item);
+        }
+    }
+    Ok(same_kind(&args[1], result))
+}
+
+fn reduce(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let mut items = sequence_items(args[1].clone())?.into_iter();
+
+    let mut acc = items.next().ok_or(
+        EvalError::EmptySequence()
+    )?;
+    for item in items {
+        acc = interp.apply(&args[0], &[acc, item])?;
+    }
+    Ok(acc)
+}
+
+fn fold_left(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let mut acc = args[1].clone();
+
+    for mut call_args in zip_sequences(&args[2..])? {
+        call_args.insert(0, acc);
+        acc = interp.apply(&args[0], &call_args)?;
+    }
+    Ok(acc)
+}
+
+fn fold_right(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let mut acc = args[1].clone();
+
+    for mut call_args in zip_sequences(&args[2..])?.into_iter().rev() {
+        call_args.push(acc);
+        acc = interp.apply(&args[0], &call_args)?;
+    }
+    Ok(acc)
+}
+
+fn any(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    for call_args in zip_sequences(&args[1..])? {
+        if apply_predicate(interp, &args[0], &call_args)? {
+            return Ok(LispEval::Bool(true));
+        }
+    }
+    Ok(LispEval::Bool(false))
+}
+
+fn every(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    for call_args in zip_sequences(&args[1..])? {
+        if !apply_predicate(interp, &args[0], &call_args)? {
+            return Ok(LispEval::Bool(false));
+        }
+    }
+    Ok(LispEval::Bool(true))
+}
+
+fn find(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    for item in sequence_items(args[1].clone())? {
+        if apply_predicate(interp, &args[0], std::slice::from_ref(&item))? {
+            return Ok(item);
+        }
+    }
+    Ok(LispEval::List(List::new()))
+}
+
+fn count(interp: &mut Interpreter, args: &[LispEval]) -> Result<LispEval, EvalError> {
+    let mut result = 0.0;
+    for call_args in zip_sequences(&args[1..])? {
+        if apply_predicate(interp, &args[0], &call_args)? {
+            result += 1.0;
+        }
+    }
+    Ok(LispEval::Number(result))
+}
@@ -1,11 +1,23 @@
 #![allow(unused)]
 
 use std::fmt;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
-
-use crate::exp::LispExp;
-use crate::env::LispEnv;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::exp::{LispExp, escape_string};
+use crate::env::{LispEnv, Scope};
+use crate::module;
+use crate::interpreter::Interpreter;
+use crate::native::NativeFn;
+use crate::list::List;
+use crate::symbol::Symbol;
+use crate::vm::{self, Closure, Param};
+use crate::resolver::{self, Local, Procedure};
+#[cfg(feature = "jit")]
+use crate::jit::{self, Native};
 
 use crate::utils::exceptions::EvalError;
 
@@ -13,26 +25,80 @@ use crate::utils::exceptions::EvalError;
 // LispEval is interpretation of LispExp
 #[derive(Clone)]
 pub enum LispEval {
+    // The result of forms evaluated only for their effects, like define
+    Void,
     Bool(bool),
     Number(f64),
-    List(Vec<LispEval>),
-    Set(HashSet<LispEval>),
-    Func(fn(&LispEval) -> Result<LispEval, EvalError>),
-    Lambda(LispLambda),
+    Str(String),
+    Symbol(Symbol),
+    List(List),
+    Vector(Rc<RefCell<Vec<LispEval>>>),
+    Set(Rc<HashSet<LispEval>>),
+    Map(Rc<HashMap<LispEval, LispEval>>),
+    Func(Rc<NativeFn>),
+    Lambda(Rc<LispLambda>),
+    Macro(Rc<LispLambda>),
 }
 
+// A procedure defined in rlisp. It keeps the module globals it was created
+// in, which its body is evaluated in.
 #[derive(Clone)]
 pub struct LispLambda {
-  params: Box<Vec<LispExp>>,
-  body: Box<LispExp>,
+  code: Code,
+  globals: usize,
+  #[cfg(feature = "jit")]
+  native: Option<Rc<Native>>,
+}
+
+// Lambdas evaluated by walking their body keep the local scope they were
+// created in, compiled ones are closures run by the virtual machine
+#[derive(Clone)]
+enum Code {
+  Tree {
+    procedure: Rc<Procedure>,
+    scope: Option<Rc<Scope>>,
+  },
+  Compiled(Closure),
+}
+
+impl LispLambda {
+
+  pub(crate) fn compiled(closure: Closure, globals: usize) -> Self {
+    LispLambda {
+      code: Code::Compiled(closure),
+      globals,
+      #[cfg(feature = "jit")]
+      native: None,
+    }
+  }
+
+  // Whether the lambda was created in the module whose globals are in use
+  pub(crate) fn is_current(&self, interp: &Interpreter) -> bool {
+    self.globals == interp.modules.current
+  }
+
+  pub(crate) fn closure(&self) -> Option<(&Closure, usize)> {
+    match &self.code {
+      Code::Compiled(closure) => Some((closure, self.globals)),
+      Code::Tree { .. } => None,
+    }
+  }
 }
 
 
+// Values compare structurally, so lists, sets and maps can be used as keys
 impl PartialEq for LispEval {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
+            (LispEval::Void, LispEval::Void) => true,
             (LispEval::Bool(a), LispEval::Bool(b)) => a == b,
             (LispEval::Number(a), LispEval::Number(b)) => a == b,
+            (LispEval::Str(a), LispEval::Str(b)) => a == b,
+            (LispEval::Symbol(a), LispEval::Symbol(b)) => a == b,
+            (LispEval::List(a), LispEval::List(b)) => a == b,
+            (LispEval::Vector(a), LispEval::Vector(b)) => Rc::ptr_eq(a, b) || a == b,
+            (LispEval::Set(a), LispEval::Set(b)) => Rc::ptr_eq(a, b) || a == b,
+            (LispEval::Map(a), LispEval::Map(b)) => Rc::ptr_eq(a, b) || a == b,
             _ => false,
         }
     }
@@ -40,34 +106,98 @@ impl PartialEq for LispEval {
 
 impl Eq for LispEval {}
 
+// Combines the hashes of unordered items independently of their order
+fn unordered_hash<T: Hash, I: Iterator<Item = T>>(items: I) -> u64 {
+    items.map(|item| {
+        let mut hasher = DefaultHasher::new();
+        item.hash(&mut hasher);
+        hasher.finish()
+    }).fold(0, u64::wrapping_add)
+}
+
 impl Hash for LispEval {
     fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            LispEval::Bool(a) => a.hash(state),
+            // 0.0 and -0.0 are equal, so they must hash the same
+            LispEval::Number(a) => (if *a == 0.0 { 0.0 } else { *a }).to_bits().hash(state),
+            LispEval::Str(a) => a.hash(state),
+            LispEval::Symbol(a) => a.hash(state),
+            LispEval::List(a) => a.hash(state),
+            // Vectors hash their current items, mutating a vector used as a key loses it
+            LispEval::Vector(a) => a.borrow().hash(state),
+            LispEval::Set(a) => unordered_hash(a.iter()).hash(state),
+            LispEval::Map(a) => unordered_hash(a.iter()).hash(state),
+            _ => {}
+        }
+    }
+}
+
+impl LispEval {
+
+    // Name of the type of the value, as returned by type-of
+    pub fn type_name(&self) -> &'static str {
         match self {
-            LispEval::Bool(a) => a.to_string().hash(state),
-            LispEval::Number(a) => a.to_string().hash(state),
-            _ => format!("{}", self).hash(state)
+            LispEval::Void => "void",
+            LispEval::Bool(_) => "bool",
+            LispEval::Number(_) => "number",
+            LispEval::Str(_) => "string",
+            LispEval::Symbol(s) if s.is_keyword() => "keyword",
+            LispEval::Symbol(_) => "symbol",
+            LispEval::List(_) => "list",
+            LispEval::Vector(_) => "vector",
+            LispEval::Set(_) => "set",
+            LispEval::Map(_) => "map",
+            LispEval::Func(_) => "native",
+            LispEval::Lambda(_) => "lambda",
+            LispEval::Macro(_) => "macro",
         }
     }
 }
 
 pub fn eval(exp: LispExp, env: &mut LispEnv) -> Result<LispEval, EvalError> {
+    let mut interp = Interpreter::with_env(std::mem::take(env));
+    let result = interp.eval(exp);
+    *env = interp.into_env();
+    result
+}
+
+pub(crate) fn eval_exp(exp: &LispExp, interp: &mut Interpreter) -> Result<LispEval, EvalError> {
 
+    interp.step()?;
     match exp {
-        LispExp::Bool(b) => Ok(LispEval::Bool(b)),
-        LispExp::Number(n) => Ok(LispEval::Number(n)),
+        LispExp::Bool(b) => Ok(LispEval::Bool(*b)),
+        LispExp::Number(n) => Ok(LispEval::Number(*n)),
+        LispExp::Str(s) => Ok(LispEval::Str(s.clone())),
+        // Keywords evaluate to themselves
+        LispExp::Symbol(s) if s.is_keyword() => Ok(LispEval::Symbol(*s)),
         LispExp::Symbol(s) => {
-            let env_value = env.get(&s);       
-            match env_value {
-                Some(x) => Ok(x.clone()),
-                None => Err(EvalError::UnknownSymbol(s.to_string()))
-            } 
+            interp.lookup(*s).ok_or_else(
+                || EvalError::UnknownSymbol(s.to_string())
+            )
         },
-        LispExp::List(list) => eval_list(list, env),
+        LispExp::Local(local) => {
+            local_value(local, interp).ok_or_else(
+                || EvalError::UnknownSymbol(local.name.to_string())
+            )
+        },
+        LispExp::List(list) => eval_list(list, interp),
+        LispExp::Procedure(procedure) => Ok(LispEval::Lambda(Rc::new(new_procedure(procedure.clone(), interp)))),
     }
 }
 
+// The value of a resolved variable. While its slot is not set, the variable
+// with the name in the enclosing procedures or the global is used.
+fn local_value(local: &Local, interp: &Interpreter) -> Option<LispEval> {
+    let scope = interp.scope.as_ref().expect("locals are evaluated in procedures");
+    scope.slot(local.depth, local.slot)
+        .or_else(|| scope.outer(local.depth, local.name))
+        .or_else(|| interp.env.get(local.name).cloned())
+}
+
 
-fn eval_list(list: Vec<LispExp>, env: &mut LispEnv
+fn eval_list(list: &[LispExp], interp: &mut Interpreter
 ) -> Result<LispEval, EvalError> {
 
     let (head, tail) = list.split_first()
@@ -76,13 +206,19 @@ fn eval_list(list: Vec<LispExp>, env: &mut LispEnv
         )?;
     match head {
         LispExp::List(first) => {
-            match eval(LispExp::List(first.clone()), env) {
-                Ok(_) => eval(LispExp::List(tail.to_vec()), env),
+            match eval_list(first, interp) {
+                Ok(_) => eval_list(tail, interp),
                 Err(e) => Err(e)
             }
         },
         LispExp::Symbol(s) => {
-            eval_symbol(s.clone(), tail, env)
+            eval_symbol(*s, tail, interp)
+        },
+        LispExp::Local(local) => {
+            let callee = local_value(local, interp).ok_or(
+                EvalError::NonDefineInThisScope
+            )?;
+            call_value(callee, tail, interp)
         },
         _ => {
             Err(EvalError::InvalidListExpression())
@@ -90,34 +226,69 @@ fn eval_list(list: Vec<LispExp>, env: &mut LispEnv
     }
 }
 
-fn eval_symbol(symbol: String, args: &[LispExp], env: &mut LispEnv
+fn eval_symbol(symbol: Symbol, args: &[LispExp], interp: &mut Interpreter
 ) -> Result<LispEval, EvalError> {
 
-    match symbol.as_str() {
-        "if" => if_statement(args, env),
-        "define" => define_variable(args, env),
-        "lambda" => define_lambda(args, env),
-        "fn" => define_function(args, env),
+    match symbol {
+        Symbol::QUOTE => quote(args),
+        Symbol::LOAD => module::load(args, interp),
+        Symbol::MODULE => module::declare(args, interp),
+        Symbol::IMPORT => module::import(args, interp),
+        Symbol::IF => if_statement(args, interp),
+        Symbol::BEGIN => eval_body(args, interp),
+        Symbol::DEFINE => define_variable(args, interp),
+        Symbol::LAMBDA => define_lambda(args, interp),
+        Symbol::FN => define_function(args, interp),
+        Symbol::DEFMACRO => define_macro(args, interp),
+        Symbol::WHILE => while_loop(args, interp),
+        Symbol::DO => do_loop(args, interp),
+        Symbol::DOTIMES => dotimes_loop(args, interp),
+        Symbol::DOLIST => dolist_loop(args, interp),
         _ => {
-            call_function(&symbol, args, env)
+            call_function(symbol, args, interp)
         }
     }
 }
 
-fn if_statement( args: &[LispExp], env: &mut LispEnv
+// (quote exp) returns the expression as data without evaluating it
+fn quote(args: &[LispExp]) -> Result<LispEval, EvalError> {
+    match args {
+        [exp] => Ok(quote_exp(exp)),
+        _ => Err(EvalError::InvalidNumberOfArguments())
+    }
+}
+
+pub(crate) fn quote_exp(exp: &LispExp) -> LispEval {
+    match exp {
+        LispExp::Bool(b) => LispEval::Bool(*b),
+        LispExp::Number(n) => LispEval::Number(*n),
+        LispExp::Str(s) => LispEval::Str(s.clone()),
+        LispExp::Symbol(s) => LispEval::Symbol(*s),
+        LispExp::Local(local) => LispEval::Symbol(local.name),
+        LispExp::List(list) => LispEval::List(list.iter().map(quote_exp).collect()),
+        LispExp::Procedure(procedure) => {
+            let mut items = vec![LispEval::Symbol(Symbol::LAMBDA)];
+            items.extend(procedure.params.iter().map(quote_exp));
+            items.push(quote_exp(&procedure.body));
+            LispEval::List(List::from(items))
+        },
+    }
+}
+
+fn if_statement( args: &[LispExp], interp: &mut Interpreter
 ) -> Result<LispEval, EvalError> {
 
     if args.len() != 3 {
         Err(EvalError::InvalidNumberOfArguments())
     }
     else {
-        let if_exp:LispEval = eval(args[0].clone(), env)?;
+        let if_exp:LispEval = eval_exp(&args[0], interp)?;
         match if_exp {
             LispEval::Bool(res) => {
                 if res {
-                    Ok(eval(args[1].clone(), env)?)
+                    Ok(eval_exp(&args[1], interp)?)
                 } else {
-                    Ok(eval(args[2].clone(), env)?)
+                    Ok(eval_exp(&args[2], interp)?)
                 }
             },
             _ => Err(EvalError::InvalidIfStatement())
@@ -125,22 +296,38 @@ fn if_statement( args: &[LispExp], env: &mut LispEnv
     }
 }
 
-fn define_variable(args: &[LispExp], env: &mut LispEnv
+// The name bound by a definition or a parameter. Other expressions are bound
+// under their printed form.
+pub(crate) fn binding_name(exp: &LispExp) -> Symbol {
+    match exp {
+        LispExp::Symbol(s) => *s,
+        LispExp::Local(local) => local.name,
+        _ => Symbol::intern(&exp.to_string()),
+    }
+}
+
+fn define_variable(args: &[LispExp], interp: &mut Interpreter
 ) -> Result<LispEval, EvalError> {
 
     if args.len() != 2 {
         return Err(EvalError::InvalidNumberOfArguments());
     } 
         
-    let variable_name = args[0].to_string();
-    let variable_value = eval(args[1].clone(), env)?;
-        
-    env.add(&variable_name, variable_value.clone());
+    let variable_value = eval_exp(&args[1], interp)?;
+
+    match (&args[0], &interp.scope) {
+        (LispExp::Local(local), Some(scope)) => scope.set_slot(local.slot, variable_value),
+        (name, _) => {
+            #[cfg(feature = "jit")]
+            let variable_value = compile_native(binding_name(name), variable_value, interp);
+            interp.define(binding_name(name), variable_value)
+        },
+    }
 
-    Ok(variable_value)
+    Ok(LispEval::Void)
 }
 
-fn define_function(args: &[LispExp], env: &mut LispEnv
+fn define_function(args: &[LispExp], interp: &mut Interpreter
 ) -> Result<LispEval, EvalError> {
 
     if args.len() < 3 {
@@ -148,103 +335,402 @@ fn define_function(args: &[LispExp], env: &mut LispEnv
     } 
     else {
 
-        let fn_name: String = args[0].to_string();
+        let fn_name = binding_name(&args[0]);
 
-        let params: Vec<LispExp> = args[1 .. args.len()-1].to_vec();
+        let params = &args[1 .. args.len()-1];
 
-        let fn_def = args.last().unwrap().clone();
+        let fn_def = args.last().unwrap();
 
-        let lambda_exp = LispEval::Lambda(
-            LispLambda {
-                params: Box::new(params),
-                body: Box::new(fn_def)
-            }
-        );
+        let lambda_exp = LispEval::Lambda(Rc::new(new_lambda(params, fn_def, interp)));
 
-        env.add(&fn_name, lambda_exp);
+        #[cfg(feature = "jit")]
+        let lambda_exp = compile_native(fn_name, lambda_exp, interp);
+        interp.define(fn_name, lambda_exp);
 
-        Ok(LispEval::Bool(true))
+        Ok(LispEval::Void)
     }    
 }
 
-fn define_lambda(args: &[LispExp], env: &mut LispEnv
+// (defmacro name params... body) defines a procedure called with its
+// arguments unevaluated, whose result is evaluated in place of the call
+fn define_macro(args: &[LispExp], interp: &mut Interpreter
+) -> Result<LispEval, EvalError> {
+
+    if args.len() < 3 {
+        return Err(EvalError::InvalidNumberOfArguments());
+    }
+    let name = binding_name(&args[0]);
+    let params = &args[1 .. args.len()-1];
+    let body = args.last().unwrap();
+
+    let macro_exp = LispEval::Macro(Rc::new(new_lambda(params, body, interp)));
+    interp.define(name, macro_exp);
+
+    Ok(LispEval::Void)
+}
+
+fn define_lambda(args: &[LispExp], interp: &mut Interpreter
 ) -> Result<LispEval, EvalError> {
 
     if args.len() < 2 {
         Err(EvalError::InvalidNumberOfArguments())            
     } 
     else {
-        let params: Vec<LispExp> = args[0 .. args.len()-1].to_vec();
-        let fn_def = args.last().unwrap().clone();
+        let params = &args[0 .. args.len()-1];
+        let fn_def = args.last().unwrap();
 
-        let lambda_exp = LispEval::Lambda(
-            LispLambda {
-                params: Box::new(params),
-                body: Box::new(fn_def)
-            }
-        );
+        let lambda_exp = LispEval::Lambda(Rc::new(new_lambda(params, fn_def, interp)));
 
         Ok(lambda_exp)
     }    
 }
 
+fn new_lambda(params: &[LispExp], body: &LispExp, interp: &Interpreter) -> LispLambda {
+    let procedure = resolver::procedure(params, body, interp);
+    new_procedure(Rc::new(procedure), interp)
+}
+
+fn new_procedure(procedure: Rc<Procedure>, interp: &Interpreter) -> LispLambda {
+    LispLambda {
+        code: Code::Tree {
+            procedure,
+            scope: interp.scope.clone(),
+        },
+        globals: interp.modules.current,
+        #[cfg(feature = "jit")]
+        native: None,
+    }
+}
+
+// Compiles a lambda defined as a global to native code, when its body only
+// computes with numbers
+#[cfg(feature = "jit")]
+fn compile_native(name: Symbol, mut value: LispEval, interp: &Interpreter) -> LispEval {
+    if interp.scope.is_some() {
+        return value;
+    }
+    if let LispEval::Lambda(lambda) = &mut value {
+        if let Some(LispLambda { code: Code::Tree { procedure, .. }, native, .. }) = Rc::get_mut(lambda) {
+            *native = jit::compile(name, procedure).map(Rc::new);
+        }
+    }
+    value
+}
+
 
-fn call_function(symbol: &str, args: &[LispExp], env: &mut LispEnv
+fn call_function(symbol: Symbol, args: &[LispExp], interp: &mut Interpreter
 ) -> Result<LispEval, EvalError> {
 
-    let env_fn = env.get(&symbol).ok_or(
+    let env_fn = interp.lookup(symbol).ok_or(
         EvalError::NonDefineInThisScope
-    )?.clone();
+    )?;
+    call_value(env_fn, args, interp)
+}
+
+fn call_value(env_fn: LispEval, args: &[LispExp], interp: &mut Interpreter
+) -> Result<LispEval, EvalError> {
+
+    if let LispEval::Macro(macro_fn) = env_fn {
+        return eval_exp(&expand_macro(&macro_fn, args, interp)?, interp);
+    }
+
+    let mut evaluated_args: Vec<LispEval> = vec![];
+    for arg in args.iter() {
+        evaluated_args.push(eval_exp(arg, interp)?);
+    }
+
+    apply(&env_fn, &evaluated_args, interp)
+}
 
-    
-    match env_fn {
+// Calls a macro with its arguments as data and returns the expression it
+// expands to
+pub(crate) fn expand_macro(macro_fn: &LispLambda, args: &[LispExp], interp: &mut Interpreter
+) -> Result<LispExp, EvalError> {
+
+    let quoted: Vec<LispEval> = args.iter().map(quote_exp).collect();
+    let expansion = apply_lambda(macro_fn, &quoted, interp)?;
+    value_to_exp(&expansion)
+}
+
+// Applies a builtin or a lambda to already evaluated arguments
+pub(crate) fn apply(func: &LispEval, args: &[LispEval], interp: &mut Interpreter
+) -> Result<LispEval, EvalError> {
+
+    match func {
         LispEval::Func(func) => {
-            let mut evaluated_args: Vec<LispEval> = vec![];
-            for arg in args.iter() {
-                evaluated_args.push(eval(arg.clone(), env)?);
-            }
-            func(&LispEval::List(evaluated_args))
+            let value = interp.nested(|interp| func.call(interp, args))?;
+            interp.charge_value(&value)?;
+            Ok(value)
         },
-        LispEval::Lambda(lambda) => {
-            if args.len() < 1 {
-                Err(EvalError::InvalidNumberOfArguments())
-            } 
-            else {
-                let ev_args: Vec<LispEval> = args[0..args.len()].iter()
-                                                .map(|a| eval(a.clone(), env)
-                                                .unwrap())
-                                                .collect();
-                
-                let mut sub_env = env.clone();
-                
-                for i  in 0 .. lambda.params.len() {
-                    let arg_def = lambda.params[i].to_string();
-                    let arg_ev = ev_args.get(i).unwrap().clone();
-                    sub_env.add(&arg_def, arg_ev);
-                }
+        LispEval::Lambda(lambda) => apply_lambda(lambda, args, interp),
+        _ => {
+            Err(EvalError::InvalidFunctionCall)
+        }
+    }
+}
 
-                let fn_exp:LispExp = *lambda.body.clone();
+// Calls a lambda in a new frame of its scope. A parameter list ending with
+// &rest name binds the remaining arguments to name as a list, other lambdas
+// take exactly one argument per parameter.
+fn apply_lambda(lambda: &LispLambda, args: &[LispEval], interp: &mut Interpreter
+) -> Result<LispEval, EvalError> {
+
+    #[cfg(feature = "jit")]
+    if let Some(result) = lambda.native.as_ref().and_then(|native| jit::call(native, lambda, args, interp)) {
+        return result;
+    }
 
-                Ok(eval(fn_exp, &mut sub_env)?)
+    let (procedure, scope) = match &lambda.code {
+        Code::Tree { procedure, scope } => (procedure, scope),
+        Code::Compiled(closure) => return vm::call(closure, lambda.globals, args, interp),
+    };
+    let frame = Scope::new(procedure.slots.clone(), scope.clone());
+
+    let mut args = args.iter();
+    for binding in &procedure.bindings {
+        match *binding {
+            Param::Rest(Some(slot)) => {
+                frame.set_slot(slot, LispEval::List(args.by_ref().cloned().collect()));
+            },
+            Param::Rest(None) => return Err(EvalError::InvalidNumberOfArguments()),
+            Param::Slot(slot) => match args.next() {
+                Some(arg) => frame.set_slot(slot, arg.clone()),
+                None => return Err(EvalError::InvalidNumberOfArguments()),
             }
         }
-        _ => {
-            Err(EvalError::InvalidFunctionCall)
+    }
+    if args.next().is_some() {
+        return Err(EvalError::InvalidNumberOfArguments());
+    }
+
+    let scope = interp.scope.replace(Rc::new(frame));
+    let globals = interp.switch_globals(lambda.globals);
+    let result = interp.nested(|interp| eval_exp(&procedure.body, interp));
+    interp.switch_globals(globals);
+    interp.scope = scope;
+    result
+}
+
+// Turns data returned by a macro back into an expression
+pub(crate) fn value_to_exp(value: &LispEval) -> Result<LispExp, EvalError> {
+    match value {
+        LispEval::Bool(b) => Ok(LispExp::Bool(*b)),
+        LispEval::Number(n) => Ok(LispExp::Number(*n)),
+        LispEval::Str(s) => Ok(LispExp::Str(s.clone())),
+        LispEval::Symbol(s) => Ok(LispExp::Symbol(*s)),
+        LispEval::List(list) if list.is_proper() => {
+            Ok(LispExp::from(list.iter().map(value_to_exp).collect::<Result<Vec<LispExp>, EvalError>>()?))
+        },
+        _ => Err(EvalError::InvalidMacroExpansion(value.to_string()))
+    }
+}
+
+// Evaluates a sequence of expressions and returns the value of the last one
+fn eval_body(body: &[LispExp], interp: &mut Interpreter
+) -> Result<LispEval, EvalError> {
+
+    let mut result = LispEval::Void;
+    for exp in body {
+        result = eval_exp(exp, interp)?;
+    }
+    Ok(result)
+}
+
+fn eval_condition(exp: &LispExp, interp: &mut Interpreter
+) -> Result<bool, EvalError> {
+
+    match eval_exp(exp, interp)? {
+        LispEval::Bool(res) => Ok(res),
+        _ => Err(EvalError::InvalidLoopStatement())
+    }
+}
+
+// Splits a loop header like (var exp ...) into the variable name and the rest
+pub(crate) fn loop_header(exp: &LispExp) -> Result<(Symbol, &[LispExp]), EvalError> {
+    if let LispExp::List(header) = exp {
+        if let Some((LispExp::Symbol(var), rest)) = header.split_first() {
+            return Ok((*var, rest));
+        }
+    }
+    Err(EvalError::InvalidLoopStatement())
+}
+
+// Runs a loop with the given variables bound in the current environment.
+// Previous bindings of the variables are restored when the loop finishes,
+// while any other definitions made by the loop body are kept.
+fn with_loop_vars<F>(vars: &[Symbol], interp: &mut Interpreter, body: F
+) -> Result<LispEval, EvalError>
+where
+    F: FnOnce(&mut Interpreter) -> Result<LispEval, EvalError>
+{
+    let saved: Vec<Option<LispEval>> = vars.iter()
+        .map(|var| interp.local(*var))
+        .collect();
+
+    let result = body(interp);
+
+    for (var, value) in vars.iter().zip(saved) {
+        match value {
+            Some(value) => interp.define(*var, value),
+            None => interp.undefine(*var),
+        }
+    }
+    result
+}
+
+pub(crate) fn sequence_items(seq: LispEval) -> Result<Vec<LispEval>, EvalError> {
+    match seq {
+        LispEval::List(list) if list.is_proper() => Ok(list.into_iter().collect()),
+        LispEval::Vector(vector) => Ok(vector.borrow().clone()),
+        LispEval::Set(set) => Ok(set.iter().cloned().collect()),
+        _ => Err(EvalError::InvalidAgrumentType())
+    }
+}
+
+// (while condition body...)
+fn while_loop(args: &[LispExp], interp: &mut Interpreter
+) -> Result<LispEval, EvalError> {
+
+    let (condition, body) = args.split_first()
+        .ok_or(
+            EvalError::InvalidNumberOfArguments()
+        )?;
+
+    while eval_condition(condition, interp)? {
+        eval_body(body, interp)?;
+    }
+    Ok(LispEval::Void)
+}
+
+// (do ((var init step)...) (test result...) body...)
+fn do_loop(args: &[LispExp], interp: &mut Interpreter
+) -> Result<LispEval, EvalError> {
+
+    if args.len() < 2 {
+        return Err(EvalError::InvalidNumberOfArguments());
+    }
+
+    let specs = match &args[0] {
+        LispExp::List(specs) => specs,
+        _ => return Err(EvalError::InvalidLoopStatement())
+    };
+    let (test, result) = match &args[1] {
+        LispExp::List(clause) => clause.split_first()
+            .ok_or(
+                EvalError::InvalidLoopStatement()
+            )?,
+        _ => return Err(EvalError::InvalidLoopStatement())
+    };
+    let body = &args[2..];
+
+    let mut vars = Vec::new();
+    let mut inits = Vec::new();
+    let mut steps = Vec::new();
+    for spec in specs.iter() {
+        let (var, rest) = loop_header(spec)?;
+        match rest {
+            [_] => steps.push(None),
+            [_, step] => steps.push(Some(step)),
+            _ => return Err(EvalError::InvalidLoopStatement())
+        }
+        inits.push(eval_exp(&rest[0], interp)?);
+        vars.push(var);
+    }
+
+    with_loop_vars(&vars, interp, |interp| {
+        for (var, init) in vars.iter().zip(inits) {
+            interp.define(*var, init);
+        }
+        loop {
+            if eval_condition(test, interp)? {
+                return eval_body(result, interp);
+            }
+            eval_body(body, interp)?;
+
+            // All steps are evaluated before any variable is updated
+            let mut updates = Vec::new();
+            for (var, step) in vars.iter().zip(&steps) {
+                if let Some(step) = step {
+                    updates.push((var, eval_exp(step, interp)?));
+                }
+            }
+            for (var, value) in updates {
+                interp.define(*var, value);
+            }
         }
+    })
+}
+
+// (dotimes (var count [result]) body...)
+fn dotimes_loop(args: &[LispExp], interp: &mut Interpreter
+) -> Result<LispEval, EvalError> {
+
+    let (header, body) = args.split_first()
+        .ok_or(
+            EvalError::InvalidNumberOfArguments()
+        )?;
+    let (var, rest) = loop_header(header)?;
+    if rest.is_empty() || rest.len() > 2 {
+        return Err(EvalError::InvalidLoopStatement());
     }
+
+    let count = match eval_exp(&rest[0], interp)? {
+        LispEval::Number(n) => n,
+        _ => return Err(EvalError::InvalidAgrumentType())
+    };
+
+    with_loop_vars(std::slice::from_ref(&var), interp, |interp| {
+        let mut i = 0.0;
+        while i < count {
+            interp.define(var, LispEval::Number(i));
+            eval_body(body, interp)?;
+            i += 1.0;
+        }
+        interp.define(var, LispEval::Number(i));
+        eval_body(&rest[1..], interp)
+    })
 }
 
+// (dolist (var sequence [result]) body...)
+fn dolist_loop(args: &[LispExp], interp: &mut Interpreter
+) -> Result<LispEval, EvalError> {
+
+    let (header, body) = args.split_first()
+        .ok_or(
+            EvalError::InvalidNumberOfArguments()
+        )?;
+    let (var, rest) = loop_header(header)?;
+    if rest.is_empty() || rest.len() > 2 {
+        return Err(EvalError::InvalidLoopStatement());
+    }
+
+    let items = sequence_items(eval_exp(&rest[0], interp)?)?;
+
+    with_loop_vars(std::slice::from_ref(&var), interp, |interp| {
+        for item in items {
+            interp.define(var, item);
+            eval_body(body, interp)?;
+        }
+        interp.undefine(var);
+        eval_body(&rest[1..], interp)
+    })
+}
 
 impl fmt::Display for LispEval {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let str:String = match self {
+            // Void results print nothing
+            LispEval::Void => String::new(),
             LispEval::Bool(b) => LispExp::Bool(*b).to_string(),
             LispEval::Number(n) => LispExp::Number(*n).to_string(),
-            LispEval::List(list) => {
-                let items:Vec<String> = list.iter().map(
+            LispEval::Str(s) => escape_string(s),
+            LispEval::Symbol(s) => s.to_string(),
+            LispEval::List(list) => list.to_string(),
+            LispEval::Vector(vector) => {
+                let items:Vec<String> = vector.borrow().iter().map(
                     |item| item.to_string()
                 ).collect();
-                format!("({})", items.join(" "))
+                format!("[{}]", items.join(" "))
             },
             LispEval::Set(set) => {
                 let items:Vec<String> = set.iter().map(
@@ -252,7 +738,13 @@ impl fmt::Display for LispEval {
                 ).collect();
                 format!("{{{}}}", items.join(" "))
             },
-            _ => format!("<function>")
+            LispEval::Map(map) => {
+                let items:Vec<String> = map.iter().map(
+                    |(key, value)| format!("{} {}", key, value)
+                ).collect();
+                format!("#{{{}}}", items.join(" "))
+            },
+            _ => "<function>".to_string()
         };
         write!(f, "{}", str)
     }
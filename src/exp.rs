@@ -1,36 +1,138 @@
 use std::fmt;
+use std::rc::Rc;
+
+use crate::symbol::Symbol;
+use crate::resolver::{Local, Procedure};
 
 use crate::utils::exceptions::ParseError;
 
+// Lists share their items, so cloning an expression never copies the tree.
+// Symbols are interned while parsing.
 #[derive(Debug, Clone)]
 pub enum LispExp {
     Bool(bool),
     Number(f64),
-    Symbol(String),
-    List(Vec<LispExp>),
+    Symbol(Symbol),
+    Str(String),
+    List(Rc<[LispExp]>),
+    // Added by the resolver before a program runs, in place of variables of
+    // procedures and of lambdas
+    Local(Local),
+    Procedure(Rc<Procedure>),
+}
+
+impl From<Vec<LispExp>> for LispExp {
+    fn from(items: Vec<LispExp>) -> Self {
+        LispExp::List(items.into())
+    }
 }
 
 pub fn tokenize(expr: String) -> Vec<String> {
-    expr
-        .replace("(", " ( ")
-        .replace(")", " ) ")
-        .replace("{", " { ")
-        .replace("}", " } ")
-        .split_whitespace()
-        .map(|x| x.to_string())
-        .collect()
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut chars = expr.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            // A '#' directly followed by '{' opens a map literal
+            '{' if token == "#" => {
+                token.push(c);
+                tokens.push(std::mem::take(&mut token));
+            },
+            '(' | ')' | '{' | '}' | '[' | ']' => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+                tokens.push(c.to_string());
+            },
+            // String literals are kept as a single token, quotes included
+            '"' => {
+                token.push(c);
+                while let Some(c) = chars.next() {
+                    token.push(c);
+                    if c == '\\' {
+                        if let Some(escaped) = chars.next() {
+                            token.push(escaped);
+                        }
+                    } else if c == '"' {
+                        break;
+                    }
+                }
+            },
+            // Comments run from ';' to the end of the line
+            ';' => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            },
+            _ if c.is_whitespace() => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            },
+            _ => token.push(c),
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    tokens
 } 
 
 
-// Parses an individual token (atom) and creates a boolean, number, or symbol expression.
+// Parses an individual token (atom) and creates a boolean, number, string or symbol expression.
 pub fn parse_token(token: &str) -> LispExp {
     if let Result::Ok(value) = token.parse::<bool>() {
         LispExp::Bool(value)
     } else if let Result::Ok(value) = token.parse::<f64>() {
         LispExp::Number(value)
+    } else if let Some(value) = parse_string(token) {
+        LispExp::Str(value)
     } else {
-        LispExp::Symbol(token.to_string())
+        LispExp::Symbol(Symbol::intern(token))
+    }
+}
+
+// Unescapes a string literal token, returns None if the token is not a
+// complete string literal
+fn parse_string(token: &str) -> Option<String> {
+    let mut chars = token.strip_prefix('"')?.chars();
+    let mut value = String::new();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return chars.next().is_none().then_some(value),
+            '\\' => match chars.next()? {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                'r' => value.push('\r'),
+                escaped => value.push(escaped),
+            },
+            _ => value.push(c),
+        }
+    }
+    None
+}
+
+pub fn escape_string(value: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            _ => escaped.push(c),
+        }
     }
+    escaped.push('"');
+    escaped
 }
 
 // Parses a vector of string tokens and creates corresponding LispExp objects
@@ -44,21 +146,15 @@ pub fn parse(tokens: &[String]) -> Result<(LispExp, &[String]), ParseError>{
         )?;
 
     match token.as_str() {
+        // '(...) is read as (quote (...))
         "'" => {
             let (next, _) = rest.split_first()
                     .ok_or(
                         ParseError::MissingToken
                     )?;
             if next == "(" {
-                parsed_result.push(LispExp::Symbol("list".to_string()));
-                let (exp,rest) = parse(&rest)?;
-                if let LispExp::List(mut value) =  exp {
-                    parsed_result.append(&mut value);
-                    Ok((LispExp::List(parsed_result), rest))
-                }
-                else {
-                    Err(ParseError::UnexpectedExpression())
-                }
+                let (exp, rest) = parse(&rest)?;
+                Ok((LispExp::from(vec![LispExp::Symbol(Symbol::QUOTE), exp]), rest))
             }
             else {
                 Err(ParseError::UnexpectedToken(next.to_string()))
@@ -71,7 +167,7 @@ pub fn parse(tokens: &[String]) -> Result<(LispExp, &[String]), ParseError>{
                         ParseError::MissingToken
                     )?;
                 if next == ")" {
-                    return Ok((LispExp::List(parsed_result), next_rest))
+                    return Ok((LispExp::from(parsed_result), next_rest))
                 }
                 else {
                     let (exp, next_rest) = parse(&rest)?;
@@ -81,21 +177,16 @@ pub fn parse(tokens: &[String]) -> Result<(LispExp, &[String]), ParseError>{
             }
         },
         "{" => {
-            parsed_result.push(LispExp::Symbol("set".to_string()));
-            loop {
-                let (next, next_rest) = rest.split_first()
-                    .ok_or(
-                        ParseError::MissingToken
-                    )?;
-                if next == "}" {
-                    return Ok((LispExp::List(parsed_result), next_rest))
-                }
-                else {
-                    let (exp, next_rest) = parse(&rest)?;
-                    parsed_result.push(exp);
-                    rest = next_rest;
-                }
-            }
+            parsed_result.push(LispExp::Symbol(Symbol::SET));
+            parse_until("}", parsed_result, rest)
+        },
+        "#{" => {
+            parsed_result.push(LispExp::Symbol(Symbol::HASH_MAP));
+            parse_until("}", parsed_result, rest)
+        },
+        "[" => {
+            parsed_result.push(LispExp::Symbol(Symbol::VECTOR));
+            parse_until("]", parsed_result, rest)
         },
         ")" => {
             Err(ParseError::UnexpectedToken(")".to_string()))
@@ -103,6 +194,17 @@ pub fn parse(tokens: &[String]) -> Result<(LispExp, &[String]), ParseError>{
         "}" => {
             Err(ParseError::UnexpectedToken(")".to_string()))
         },
+        "]" => {
+            Err(ParseError::UnexpectedToken("]".to_string()))
+        },
+        // 'exp is read as (quote exp)
+        _ if token.len() > 1 && token.starts_with('\'') => {
+            let quoted = vec![LispExp::Symbol(Symbol::QUOTE), parse_token(&token[1..])];
+            Ok((LispExp::from(quoted), rest))
+        },
+        _ if token.starts_with('"') && parse_string(token).is_none() => {
+            Err(ParseError::UnterminatedString())
+        },
         _ => {
             Ok((parse_token(&token), rest))
         }
@@ -111,18 +213,45 @@ pub fn parse(tokens: &[String]) -> Result<(LispExp, &[String]), ParseError>{
 
 
 
+// Parses expressions until the closing token and appends them to the items
+fn parse_until<'a>(close: &str, mut items: Vec<LispExp>, mut rest: &'a [String]
+) -> Result<(LispExp, &'a [String]), ParseError> {
+    loop {
+        let (next, next_rest) = rest.split_first()
+            .ok_or(
+                ParseError::MissingToken
+            )?;
+        if next == close {
+            return Ok((LispExp::from(items), next_rest))
+        }
+        else {
+            let (exp, next_rest) = parse(rest)?;
+            items.push(exp);
+            rest = next_rest;
+        }
+    }
+}
+
 impl fmt::Display for LispExp {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let str:String = match self {
             LispExp::Bool(b) => b.to_string(),
             LispExp::Number(n) => n.to_string(),
             LispExp::Symbol(s) => s.to_string(),
+            LispExp::Local(local) => local.name.to_string(),
+            LispExp::Str(s) => escape_string(s),
             LispExp::List(list) => {
                 let items:Vec<String> = list.iter().map(
                     |item| item.to_string()
                 ).collect();
                 format!("({})", items.join(" "))
             },
+            LispExp::Procedure(procedure) => {
+                let items:Vec<String> = procedure.params.iter().map(
+                    |item| item.to_string()
+                ).collect();
+                format!("(lambda {} {})", items.join(" "), procedure.body)
+            },
         };
         write!(f, "{}", str)
     }
@@ -0,0 +1,362 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::debug;
+
+use crate::exp::{LispExp, tokenize, parse};
+use crate::env::{LispEnv, Scope, default_env, bare_env};
+use crate::module::{Modules, in_file};
+use crate::vm;
+use crate::resolver::resolve;
+use crate::optimizer::optimize;
+use crate::eval::{LispEval, eval_exp, apply};
+use crate::native::{NativeFn, Arity};
+use crate::convert::{IntoLispArgs, FromLisp};
+use crate::limits::{Limits, Usage, Resource, allocated_size};
+use crate::symbol::Symbol;
+
+use crate::utils::exceptions::{EvalError, LispError};
+
+// How expressions are evaluated: by walking the expression tree, or by
+// compiling them to bytecode run by a virtual machine. Both give the same
+// results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Engine {
+    #[default]
+    Tree,
+    Vm,
+}
+
+// Embeddable rlisp interpreter. It owns the global environment and is the
+// evaluation context passed to every builtin.
+//
+//     let mut interp = Interpreter::new();
+//     interp.eval_str("(fn plus a b (+ a b))")?;
+//     let sum: f64 = interp.call("plus", (3.0, 4.0))?;
+pub struct Interpreter {
+    pub(crate) env: LispEnv,
+    pub(crate) scope: Option<Rc<Scope>>,
+    pub(crate) modules: Modules,
+    pub(crate) fs_root: Option<PathBuf>,
+    limits: Limits,
+    usage: Usage,
+    interrupt: Arc<AtomicBool>,
+    engine: Engine,
+    optimize: bool,
+}
+
+impl Interpreter {
+
+    pub fn new() -> Self {
+        Interpreter::with_env(default_env())
+    }
+
+    // An interpreter with the builtins only, without the prelude. Imported
+    // modules start without the prelude as well.
+    pub fn bare() -> Self {
+        let mut interp = Interpreter::with_env(bare_env());
+        interp.modules.bare = true;
+        interp
+    }
+
+    pub fn with_env(env: LispEnv) -> Self {
+        Interpreter {
+            env,
+            scope: None,
+            modules: Modules::default(),
+            fs_root: None,
+            limits: Limits::default(),
+            usage: Usage::default(),
+            interrupt: Arc::new(AtomicBool::new(false)),
+            engine: Engine::default(),
+            optimize: false,
+        }
+    }
+
+    pub fn env(&self) -> &LispEnv {
+        &self.env
+    }
+
+    pub fn into_env(mut self) -> LispEnv {
+        self.switch_globals(0);
+        self.env
+    }
+
+    // Enables the file builtins for files inside the directory tree. They fail
+    // with a permission error when this is not called.
+    pub fn allow_fs<P: AsRef<Path>>(&mut self, root: P) -> io::Result<()> {
+        self.fs_root = Some(root.as_ref().canonicalize()?);
+        Ok(())
+    }
+
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.engine = engine;
+    }
+
+    pub fn engine(&self) -> Engine {
+        self.engine
+    }
+
+    // Optimizes programs before evaluating them: constant calls of pure
+    // builtins are computed, dead if branches removed and small procedures
+    // inlined. Builtins and inlined procedures must not be redefined later.
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    pub fn optimize(&self) -> bool {
+        self.optimize
+    }
+
+    // Limits the resources used by evaluation, exceeding one fails with
+    // EvalError::ResourceExhausted. The usage counted so far is reset.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+        self.reset_usage();
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    pub fn usage(&self) -> Usage {
+        self.usage
+    }
+
+    // Starts counting fuel and memory from zero again, for instance before
+    // evaluating the next untrusted snippet
+    pub fn reset_usage(&mut self) {
+        self.usage = Usage { depth: self.usage.depth, ..Usage::default() };
+    }
+
+    // A flag stopping the running evaluation with EvalError::Interrupted when
+    // set, from another thread or a signal handler. It is cleared once the
+    // evaluation stopped.
+    //
+    //     let interrupt = interp.interrupt_handle();
+    //     thread::spawn(move || interrupt.store(true, Ordering::Relaxed));
+    pub fn interrupt_handle(&self) -> Arc<AtomicBool> {
+        self.interrupt.clone()
+    }
+
+    // Adds a directory searched for modules by import
+    pub fn add_search_path<P: Into<PathBuf>>(&mut self, path: P) {
+        self.modules.search_path.push(path.into());
+    }
+
+    pub fn eval(&mut self, exp: LispExp) -> Result<LispEval, EvalError> {
+        self.eval_program(vec![exp])
+    }
+
+    // Evaluates every expression in the source and returns the value of the
+    // last one, or void for an empty source. Nothing is evaluated when the
+    // source does not parse or uses unbound variables.
+    pub fn eval_str(&mut self, source: &str) -> Result<LispEval, LispError> {
+        let tokens = tokenize(source.to_string());
+        let mut rest = &tokens[..];
+
+        let mut program = Vec::new();
+        while !rest.is_empty() {
+            let (exp, next) = parse(rest)?;
+            program.push(exp);
+            rest = next;
+        }
+        Ok(self.eval_program(program)?)
+    }
+
+    // Optimizes the program when enabled and resolves its variables, then
+    // evaluates its expressions in order
+    pub(crate) fn eval_program(&mut self, program: Vec<LispExp>) -> Result<LispEval, EvalError> {
+        let program = if self.optimize { optimize(program, self) } else { program };
+        let resolved = resolve(&program, self)?;
+
+        let mut value = LispEval::Void;
+        for (exp, resolved) in program.iter().zip(&resolved) {
+            value = match self.engine {
+                Engine::Tree => eval_exp(resolved, self)?,
+                Engine::Vm => vm::eval(exp, self)?,
+            };
+            debug!("{} -> {}", exp, value);
+        }
+        Ok(value)
+    }
+
+    // Evaluates a file, files it loads are resolved relative to its directory.
+    // While it runs, load and import may read the files of its directory tree.
+    pub fn eval_file<P: AsRef<Path>>(&mut self, path: P) -> Result<LispEval, LispError> {
+        let source = fs::read_to_string(&path)?;
+        let path = path.as_ref().canonicalize()?;
+
+        let root = path.parent().map(Path::to_path_buf);
+        let previous = std::mem::replace(&mut self.modules.root, root);
+        let result = in_file(&path, self, |interp| interp.eval_str(&source));
+        self.modules.root = previous;
+        result?
+    }
+
+    // Registers a Rust closure as a global builtin
+    pub fn define_native<F>(&mut self, name: &str, arity: Arity, doc: &str, body: F)
+    where
+        F: Fn(&mut Interpreter, &[LispEval]) -> Result<LispEval, EvalError> + 'static
+    {
+        self.env.add_native(NativeFn::new(name, arity, doc, body));
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<LispEval> {
+        self.env.get(name).cloned()
+    }
+
+    pub fn set_global(&mut self, name: &str, value: LispEval) {
+        self.env.add(name, value);
+    }
+
+    // Calls a global procedure by name, converting the arguments and the result
+    //
+    //     let sum = interp.call::<(f64, f64), f64>("plus", (3.0, 4.0))?;
+    pub fn call<A, R>(&mut self, name: &str, args: A) -> Result<R, EvalError>
+    where
+        A: IntoLispArgs,
+        R: FromLisp
+    {
+        let func = self.env.get(name).cloned().ok_or(
+            EvalError::UnknownSymbol(name.to_string())
+        )?;
+        let result = self.apply(&func, &args.into_lisp_args())?;
+        R::from_lisp(result)
+    }
+
+    // Looks a symbol up in the local scopes, then in the global environment
+    pub(crate) fn lookup(&self, symbol: Symbol) -> Option<LispEval> {
+        match self.scope.as_ref().and_then(|scope| scope.get(symbol)) {
+            Some(value) => Some(value),
+            None => self.env.get(symbol).cloned()
+        }
+    }
+
+    // Binds a symbol in the innermost scope, or globally outside of procedures
+    pub(crate) fn define(&mut self, symbol: Symbol, value: LispEval) {
+        match &self.scope {
+            Some(scope) => scope.add(symbol, value),
+            None => self.env.add(symbol, value),
+        }
+    }
+
+    // The binding of a symbol in the innermost scope only
+    pub(crate) fn local(&self, symbol: Symbol) -> Option<LispEval> {
+        match &self.scope {
+            Some(scope) => scope.get_local(symbol),
+            None => self.env.get(symbol).cloned(),
+        }
+    }
+
+    pub(crate) fn undefine(&mut self, symbol: Symbol) {
+        match &self.scope {
+            Some(scope) => scope.remove(symbol),
+            None => self.env.remove(symbol),
+        }
+    }
+
+    // Called for every evaluated expression, uses one unit of fuel and stops
+    // when interrupted
+    pub(crate) fn step(&mut self) -> Result<(), EvalError> {
+        self.use_fuel(1)
+    }
+
+    fn use_fuel(&mut self, units: usize) -> Result<(), EvalError> {
+        if self.interrupt.load(Ordering::Relaxed) {
+            self.interrupt.store(false, Ordering::Relaxed);
+            return Err(EvalError::Interrupted);
+        }
+        self.usage.fuel = self.usage.fuel.saturating_add(units as u64);
+        match self.limits.fuel {
+            Some(fuel) if self.usage.fuel > fuel => Err(EvalError::ResourceExhausted(Resource::Fuel)),
+            _ => Ok(())
+        }
+    }
+
+    // Called by builtins before they build `count` items of `size` bytes. Each
+    // item uses a unit of fuel and the items have to fit in the memory left,
+    // the value is counted once the builtin returns it.
+    pub(crate) fn reserve(&mut self, count: usize, size: usize) -> Result<(), EvalError> {
+        self.use_fuel(count)?;
+        let needed = count.saturating_mul(size);
+        match self.limits.max_memory {
+            Some(memory) if self.usage.memory.saturating_add(needed) > memory => {
+                Err(EvalError::ResourceExhausted(Resource::Memory))
+            },
+            _ => Ok(())
+        }
+    }
+
+    // Runs a procedure call one level deeper
+    pub(crate) fn nested<F>(&mut self, call: F) -> Result<LispEval, EvalError>
+    where
+        F: FnOnce(&mut Interpreter) -> Result<LispEval, EvalError>
+    {
+        self.enter_call()?;
+        let result = call(self);
+        self.exit_call();
+        result
+    }
+
+    pub(crate) fn enter_call(&mut self) -> Result<(), EvalError> {
+        if matches!(self.limits.max_depth, Some(depth) if self.usage.depth >= depth) {
+            return Err(EvalError::ResourceExhausted(Resource::Depth));
+        }
+        self.usage.depth += 1;
+        Ok(())
+    }
+
+    pub(crate) fn exit_call(&mut self) {
+        self.usage.depth -= 1;
+    }
+
+    // Counts memory allocated for values in bytes, only while it is limited
+    pub(crate) fn charge_memory(&mut self, size: usize) -> Result<(), EvalError> {
+        if self.limits.max_memory.is_none() {
+            return Ok(());
+        }
+        self.usage.memory = self.usage.memory.saturating_add(size);
+        match self.limits.max_memory {
+            Some(memory) if self.usage.memory > memory => Err(EvalError::ResourceExhausted(Resource::Memory)),
+            _ => Ok(())
+        }
+    }
+
+    // Counts the memory of a value built by a builtin
+    pub(crate) fn charge_value(&mut self, value: &LispEval) -> Result<(), EvalError> {
+        if self.limits.max_memory.is_none() {
+            return Ok(());
+        }
+        self.charge_memory(allocated_size(value))
+    }
+
+    // Makes the global environment of a module the active one and returns
+    // the previously active module
+    pub(crate) fn switch_globals(&mut self, module: usize) -> usize {
+        let previous = self.modules.current;
+        if module != previous {
+            let env = self.modules.parked.remove(&module).unwrap_or_default();
+            let env = std::mem::replace(&mut self.env, env);
+            self.modules.parked.insert(previous, env);
+            self.modules.current = module;
+        }
+        previous
+    }
+
+    // Calls a builtin or a lambda with already evaluated arguments
+    pub fn apply(&mut self, func: &LispEval, args: &[LispEval]
+    ) -> Result<LispEval, EvalError> {
+        apply(func, args, self)
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter::new()
+    }
+}
@@ -0,0 +1,376 @@
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use cranelift_codegen::ir::{types, AbiParam, Block, FuncRef, InstBuilder, MemFlags, Type, Value};
+use cranelift_codegen::ir::condcodes::FloatCC;
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Linkage, Module};
+
+use crate::exp::LispExp;
+use crate::eval::{LispEval, LispLambda};
+use crate::interpreter::Interpreter;
+use crate::limits::Limits;
+use crate::resolver::Procedure;
+use crate::symbol::Symbol;
+use crate::vm::Param;
+
+use crate::utils::exceptions::EvalError;
+
+// Builtins native code computes itself, with the instruction for each
+const ARITHMETIC: [&str; 5] = ["+", "-", "*", "/", "%"];
+const COMPARISONS: [(&str, FloatCC); 5] = [
+    ("=", FloatCC::Equal),
+    ("<", FloatCC::LessThan),
+    (">", FloatCC::GreaterThan),
+    ("<=", FloatCC::LessThanOrEqual),
+    (">=", FloatCC::GreaterThanOrEqual),
+];
+
+// Shared by native code and the interpreter while it runs. Native code stops
+// at the next call once the interrupt flag is set.
+#[repr(C)]
+struct Context {
+    interrupt: *const AtomicBool,
+    interrupted: u8,
+}
+
+const INTERRUPT_OFFSET: i32 = 0;
+const INTERRUPTED_OFFSET: i32 = std::mem::size_of::<*const AtomicBool>() as i32;
+
+type Entry = unsafe extern "C" fn(*mut Context, *const f64) -> f64;
+
+// f64 remainder, which has no instruction
+extern "C" fn remainder(x: f64, y: f64) -> f64 {
+    x % y
+}
+
+// A procedure compiled to native code. It is used in place of the body for
+// calls with numbers only, while its name and the builtins it calls are still
+// bound to the procedure and to those builtins.
+pub(crate) struct Native {
+    name: Symbol,
+    arity: usize,
+    builtins: Vec<Symbol>,
+    module: Option<JITModule>,
+    entry: Entry,
+}
+
+impl Drop for Native {
+    fn drop(&mut self) {
+        if let Some(module) = self.module.take() {
+            // Nothing runs the code once the procedure is dropped
+            unsafe { module.free_memory() };
+        }
+    }
+}
+
+// What an expression of the body computes
+#[derive(Clone, Copy)]
+enum Typed {
+    Number(Value),
+    Bool(Value),
+}
+
+// Compiles a procedure bound to a global name. Its parameters must be plain
+// and its body only made of numbers, parameters, calls of the arithmetic
+// builtins and comparisons, if forms and calls of the procedure itself, with
+// a number as result. Other procedures are left to the interpreter.
+pub(crate) fn compile(name: Symbol, procedure: &Procedure) -> Option<Native> {
+    let arity = procedure.params.len();
+    let plain = procedure.bindings.iter().enumerate().all(|(i, binding)| matches!(binding, Param::Slot(slot) if *slot as usize == i));
+    if arity == 0 || !plain || procedure.slots.len() != arity {
+        return None;
+    }
+    let mut builtins = Vec::new();
+    if !check(&procedure.body, name, arity, &mut builtins) {
+        return None;
+    }
+
+    let mut jit = JITBuilder::with_flags(&[("opt_level", "speed")], default_libcall_names()).ok()?;
+    jit.symbol("rlisp_remainder", remainder as *const u8);
+    let mut module = JITModule::new(jit);
+
+    let pointer = module.target_config().pointer_type();
+    let mut signature = module.make_signature();
+    signature.params.push(AbiParam::new(pointer));
+    signature.params.extend((0..arity).map(|_| AbiParam::new(types::F64)));
+    signature.returns.push(AbiParam::new(types::F64));
+    let body_id = module.declare_function(name.name(), Linkage::Local, &signature).ok()?;
+
+    let mut remainder_signature = module.make_signature();
+    remainder_signature.params.extend([AbiParam::new(types::F64), AbiParam::new(types::F64)]);
+    remainder_signature.returns.push(AbiParam::new(types::F64));
+    let remainder_id = module.declare_function("rlisp_remainder", Linkage::Import, &remainder_signature).ok()?;
+
+    let mut builder_context = FunctionBuilderContext::new();
+    let mut context = module.make_context();
+    context.func.signature = signature.clone();
+    {
+        let mut builder = FunctionBuilder::new(&mut context.func, &mut builder_context);
+        let own = module.declare_func_in_func(body_id, builder.func);
+        let remainder = module.declare_func_in_func(remainder_id, builder.func);
+
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        let params = builder.block_params(entry).to_vec();
+        let interrupted = builder.create_block();
+
+        // Checks the interrupt flag once per call, other threads set it
+        let flags = MemFlags::trusted();
+        let interrupt = builder.ins().load(pointer, flags, params[0], INTERRUPT_OFFSET);
+        let flag = builder.ins().atomic_load(types::I8, flags, interrupt);
+        let body = builder.create_block();
+        builder.ins().brif(flag, interrupted, &[], body, &[]);
+
+        builder.switch_to_block(interrupted);
+        let one = builder.ins().iconst(types::I8, 1);
+        builder.ins().store(flags, one, params[0], INTERRUPTED_OFFSET);
+        let zero = builder.ins().f64const(0.0);
+        builder.ins().return_(&[zero]);
+
+        builder.switch_to_block(body);
+        let mut compiler = Compiler { builder, params, own, remainder, interrupted };
+        let result = match compiler.exp(&procedure.body) {
+            Typed::Number(value) => value,
+            Typed::Bool(_) => unreachable!(),
+        };
+        compiler.builder.ins().return_(&[result]);
+        compiler.builder.seal_all_blocks();
+        compiler.builder.finalize();
+    }
+    module.define_function(body_id, &mut context).ok()?;
+    module.clear_context(&mut context);
+
+    // The entry point takes the arguments as an array
+    let mut entry_signature = module.make_signature();
+    entry_signature.params.extend([AbiParam::new(pointer), AbiParam::new(pointer)]);
+    entry_signature.returns.push(AbiParam::new(types::F64));
+    let entry_id = module.declare_anonymous_function(&entry_signature).ok()?;
+    context.func.signature = entry_signature;
+    {
+        let mut builder = FunctionBuilder::new(&mut context.func, &mut builder_context);
+        let body = module.declare_func_in_func(body_id, builder.func);
+        let block = builder.create_block();
+        builder.append_block_params_for_function_params(block);
+        builder.switch_to_block(block);
+        let (context, args) = (builder.block_params(block)[0], builder.block_params(block)[1]);
+
+        let mut call_args = vec![context];
+        for i in 0..arity {
+            call_args.push(builder.ins().load(types::F64, MemFlags::trusted(), args, (i * 8) as i32));
+        }
+        let call = builder.ins().call(body, &call_args);
+        let result = builder.inst_results(call)[0];
+        builder.ins().return_(&[result]);
+        builder.seal_all_blocks();
+        builder.finalize();
+    }
+    module.define_function(entry_id, &mut context).ok()?;
+    module.finalize_definitions().ok()?;
+
+    let code = module.get_finalized_function(entry_id);
+    Some(Native {
+        name,
+        arity,
+        builtins,
+        module: Some(module),
+        entry: unsafe { std::mem::transmute::<*const u8, Entry>(code) },
+    })
+}
+
+// Runs the native code of a lambda when it can stand in for its body: the
+// arguments are numbers, no limits are set, and the names the body uses are
+// bound as when it was compiled. Returns none to evaluate the body instead.
+pub(crate) fn call(native: &Native, lambda: &LispLambda, args: &[LispEval], interp: &mut Interpreter
+) -> Option<Result<LispEval, EvalError>> {
+
+    if args.len() != native.arity || interp.limits() != Limits::default() || !lambda.is_current(interp) {
+        return None;
+    }
+    let bound = matches!(interp.env.get(native.name), Some(LispEval::Lambda(l)) if std::ptr::eq(Rc::as_ptr(l), lambda));
+    let builtins = native.builtins.iter().all(|name| {
+        matches!(interp.env.get(*name), Some(LispEval::Func(func)) if func.name == name.name())
+    });
+    if !bound || !builtins {
+        return None;
+    }
+    let numbers: Option<Vec<f64>> = args.iter().map(|arg| match arg {
+        LispEval::Number(n) => Some(*n),
+        _ => None
+    }).collect();
+    let numbers = numbers?;
+
+    let interrupt = interp.interrupt_handle();
+    let mut context = Context { interrupt: Arc::as_ptr(&interrupt), interrupted: 0 };
+    let result = unsafe { (native.entry)(&mut context, numbers.as_ptr()) };
+
+    if context.interrupted != 0 {
+        interrupt.store(false, Ordering::Relaxed);
+        return Some(Err(EvalError::Interrupted));
+    }
+    Some(Ok(LispEval::Number(result)))
+}
+
+// Whether an expression can be compiled, with a number as value, collecting
+// the builtins it calls
+fn check(exp: &LispExp, name: Symbol, arity: usize, builtins: &mut Vec<Symbol>) -> bool {
+    number_type(exp, name, arity, builtins) == Some(true)
+}
+
+// Some(true) for numbers, Some(false) for booleans, none when the expression
+// cannot be compiled
+fn number_type(exp: &LispExp, name: Symbol, arity: usize, builtins: &mut Vec<Symbol>) -> Option<bool> {
+    let items = match exp {
+        LispExp::Number(_) => return Some(true),
+        LispExp::Bool(_) => return Some(false),
+        LispExp::Local(local) if local.depth == 0 && (local.slot as usize) < arity => return Some(true),
+        LispExp::List(items) => items,
+        _ => return None
+    };
+    let (head, args) = match items.split_first() {
+        Some((LispExp::Symbol(head), args)) => (*head, args),
+        _ => return None
+    };
+    let numbers = |builtins: &mut Vec<Symbol>| args.iter().all(|arg| check(arg, name, arity, builtins));
+    if head == Symbol::IF {
+        let [test, then, otherwise] = args else { return None };
+        let test_is_bool = !number_type(test, name, arity, builtins)?;
+        let then_type = number_type(then, name, arity, builtins)?;
+        let branches_agree = number_type(otherwise, name, arity, builtins)? == then_type;
+        return (test_is_bool && branches_agree).then_some(then_type);
+    }
+    if head == name {
+        return (args.len() == arity && numbers(builtins)).then_some(true);
+    }
+    let arithmetic = ARITHMETIC.contains(&head.name());
+    let comparison = COMPARISONS.iter().any(|(op, _)| *op == head.name());
+    let min_args = if arithmetic { 1 } else { 2 };
+    if !(arithmetic || comparison) || args.len() < min_args || !numbers(builtins) {
+        return None;
+    }
+    if !builtins.contains(&head) {
+        builtins.push(head);
+    }
+    Some(arithmetic)
+}
+
+struct Compiler<'a> {
+    builder: FunctionBuilder<'a>,
+    // The context pointer followed by the parameters
+    params: Vec<Value>,
+    own: FuncRef,
+    remainder: FuncRef,
+    interrupted: Block,
+}
+
+impl<'a> Compiler<'a> {
+
+    fn number(&mut self, exp: &LispExp) -> Value {
+        match self.exp(exp) {
+            Typed::Number(value) => value,
+            Typed::Bool(_) => unreachable!(),
+        }
+    }
+
+    fn exp(&mut self, exp: &LispExp) -> Typed {
+        let items = match exp {
+            LispExp::Number(n) => return Typed::Number(self.builder.ins().f64const(*n)),
+            LispExp::Bool(b) => return Typed::Bool(self.builder.ins().iconst(types::I8, i64::from(*b))),
+            LispExp::Local(local) => return Typed::Number(self.params[local.slot as usize + 1]),
+            LispExp::List(items) => items,
+            _ => unreachable!()
+        };
+        let (head, args) = match items.split_first() {
+            Some((LispExp::Symbol(head), args)) => (*head, args),
+            _ => unreachable!()
+        };
+        if head == Symbol::IF {
+            return self.if_form(&args[0], &args[1], &args[2]);
+        }
+        let values: Vec<Value> = args.iter().map(|arg| self.number(arg)).collect();
+
+        if let Some((_, cc)) = COMPARISONS.iter().find(|(op, _)| *op == head.name()) {
+            // Like the builtins, only the last two numbers are compared
+            let [.., x, y] = values[..] else { unreachable!() };
+            return Typed::Bool(self.builder.ins().fcmp(*cc, x, y));
+        }
+        match head.name() {
+            "+" => Typed::Number(self.fold(&values, |b, x, y| b.ins().fadd(x, y))),
+            "-" => Typed::Number(self.fold(&values, |b, x, y| b.ins().fsub(x, y))),
+            "*" => Typed::Number(self.fold(&values, |b, x, y| b.ins().fmul(x, y))),
+            "/" => Typed::Number(self.fold(&values, |b, x, y| b.ins().fdiv(x, y))),
+            "%" => {
+                let remainder = self.remainder;
+                Typed::Number(self.fold(&values, |b, x, y| {
+                    let call = b.ins().call(remainder, &[x, y]);
+                    b.inst_results(call)[0]
+                }))
+            },
+            _ => Typed::Number(self.call_own(values)),
+        }
+    }
+
+    // Combines numbers from the left, a single number is returned as it is
+    fn fold<F>(&mut self, values: &[Value], op: F) -> Value
+    where
+        F: Fn(&mut FunctionBuilder<'a>, Value, Value) -> Value
+    {
+        let mut result = values[0];
+        for value in &values[1..] {
+            result = op(&mut self.builder, result, *value);
+        }
+        result
+    }
+
+    fn call_own(&mut self, values: Vec<Value>) -> Value {
+        let mut args = vec![self.params[0]];
+        args.extend(values);
+        let call = self.builder.ins().call(self.own, &args);
+        let result = self.builder.inst_results(call)[0];
+
+        // Returns at once when the call was interrupted
+        let interrupted = self.builder.ins().load(types::I8, MemFlags::trusted(), self.params[0], INTERRUPTED_OFFSET);
+        let next = self.builder.create_block();
+        self.builder.ins().brif(interrupted, self.interrupted, &[], next, &[]);
+        self.builder.switch_to_block(next);
+        result
+    }
+
+    fn if_form(&mut self, test: &LispExp, then: &LispExp, otherwise: &LispExp) -> Typed {
+        let test = match self.exp(test) {
+            Typed::Bool(value) => value,
+            Typed::Number(_) => unreachable!(),
+        };
+        let then_block = self.builder.create_block();
+        let else_block = self.builder.create_block();
+        let merge = self.builder.create_block();
+        self.builder.ins().brif(test, then_block, &[], else_block, &[]);
+
+        self.builder.switch_to_block(then_block);
+        let then_value = self.exp(then);
+        let (value, ty) = untyped(then_value);
+        self.builder.append_block_param(merge, ty);
+        self.builder.ins().jump(merge, &[value]);
+
+        self.builder.switch_to_block(else_block);
+        let (value, _) = untyped(self.exp(otherwise));
+        self.builder.ins().jump(merge, &[value]);
+
+        self.builder.switch_to_block(merge);
+        let value = self.builder.block_params(merge)[0];
+        match then_value {
+            Typed::Number(_) => Typed::Number(value),
+            Typed::Bool(_) => Typed::Bool(value),
+        }
+    }
+}
+
+fn untyped(typed: Typed) -> (Value, Type) {
+    match typed {
+        Typed::Number(value) => (value, types::F64),
+        Typed::Bool(value) => (value, types::I8),
+    }
+}
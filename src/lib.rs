@@ -1,7 +1,30 @@
+// Vectors are mutable but hash by their current items, so values containing
+// them are still usable as set items and map keys
+#![allow(clippy::mutable_key_type)]
+
 pub mod exp;
 pub mod eval;
+pub mod list;
+pub mod symbol;
 pub mod env;
+pub mod native;
+pub mod interpreter;
+pub mod limits;
+mod module;
+mod resolver;
+mod optimizer;
+mod compiler;
+mod vm;
+#[cfg(feature = "jit")]
+mod jit;
+pub mod convert;
+#[cfg(feature = "serde")]
+pub mod de;
+#[cfg(feature = "serde")]
+pub mod ser;
 pub mod utils {
     pub mod exceptions;
     pub mod reader;
 }
+
+pub use interpreter::{Interpreter, Engine};
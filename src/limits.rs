@@ -0,0 +1,68 @@
+use std::mem::size_of;
+use std::rc::Rc;
+
+use crate::eval::LispEval;
+use crate::list::Pair;
+
+// A resource an interpreter can run out of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resource {
+    Fuel,
+    Depth,
+    Memory,
+}
+
+// Limits on the evaluation of untrusted code, None means unlimited.
+//
+// Fuel is the number of evaluated expressions, or of calls and loop
+// iterations with the virtual machine, and of the items builtins build,
+// depth the number of nested procedure calls and memory the total size in
+// bytes of the values built by builtins. Every nested call uses Rust stack, so max_depth must be low
+// enough for the stack of the thread running the interpreter.
+//
+//     interp.set_limits(Limits { fuel: Some(100_000), ..Limits::default() });
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub fuel: Option<u64>,
+    pub max_depth: Option<usize>,
+    pub max_memory: Option<usize>,
+}
+
+// Resources used since the limits were set or the usage was reset. Memory
+// is only counted while it is limited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub fuel: u64,
+    pub depth: usize,
+    pub memory: usize,
+}
+
+// Approximate size of the heap memory a value adds. List cells and
+// collections shared with other values were already counted when they were
+// built.
+pub(crate) fn allocated_size(value: &LispEval) -> usize {
+    match value {
+        LispEval::Str(s) => s.len(),
+        LispEval::List(list) => {
+            list.unshared().map(
+                |item| size_of::<Pair>() + allocated_size(item)
+            ).sum()
+        },
+        LispEval::Vector(vector) if Rc::strong_count(vector) == 1 => {
+            vector.borrow().iter().map(
+                |item| size_of::<LispEval>() + allocated_size(item)
+            ).sum()
+        },
+        LispEval::Set(set) if Rc::strong_count(set) == 1 => {
+            set.iter().map(
+                |item| size_of::<LispEval>() + allocated_size(item)
+            ).sum()
+        },
+        LispEval::Map(map) if Rc::strong_count(map) == 1 => {
+            map.iter().map(
+                |(key, value)| 2 * size_of::<LispEval>() + allocated_size(key) + allocated_size(value)
+            ).sum()
+        },
+        _ => 0
+    }
+}
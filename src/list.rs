@@ -0,0 +1,224 @@
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::rc::Rc;
+
+use crate::eval::LispEval;
+
+// A cons cell. The cdr of a proper list is another list, any other value
+// makes the list improper (a dotted pair).
+pub struct Pair {
+    pub car: LispEval,
+    pub cdr: LispEval,
+}
+
+// Persistent singly linked list built from shared cons cells, so cons, car
+// and cdr are O(1) and lists share their tails.
+#[derive(Clone, Default)]
+pub struct List(Option<Rc<Pair>>);
+
+impl List {
+
+    pub fn new() -> Self {
+        List(None)
+    }
+
+    pub fn cons(car: LispEval, cdr: LispEval) -> Self {
+        List(Some(Rc::new(Pair { car, cdr })))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_none()
+    }
+
+    pub fn car(&self) -> Option<&LispEval> {
+        self.0.as_ref().map(|pair| &pair.car)
+    }
+
+    pub fn cdr(&self) -> Option<&LispEval> {
+        self.0.as_ref().map(|pair| &pair.cdr)
+    }
+
+    // Iterates over the items of the list, stopping before an improper tail
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            next: self.0.as_deref()
+        }
+    }
+
+    // Number of items, not counting an improper tail
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    // The value ending an improper list, if any
+    pub fn improper_tail(&self) -> Option<&LispEval> {
+        let mut pair = self.0.as_deref()?;
+        loop {
+            match &pair.cdr {
+                LispEval::List(List(Some(next))) => pair = next.as_ref(),
+                LispEval::List(List(None)) => return None,
+                tail => return Some(tail),
+            }
+        }
+    }
+
+    // Items of the leading cells referenced by this list only
+    pub(crate) fn unshared(&self) -> impl Iterator<Item = &LispEval> {
+        let mut next = self.0.as_ref().filter(|pair| Rc::strong_count(pair) == 1);
+        std::iter::from_fn(move || {
+            let pair = next?;
+            next = match &pair.cdr {
+                LispEval::List(List(Some(cdr))) if Rc::strong_count(cdr) == 1 => Some(cdr),
+                _ => None
+            };
+            Some(&pair.car)
+        })
+    }
+
+    pub fn is_proper(&self) -> bool {
+        self.improper_tail().is_none()
+    }
+
+    // Builds a list from the items ending with the given tail
+    pub fn from_items_with_tail(items: Vec<LispEval>, tail: LispEval) -> LispEval {
+        items.into_iter().rev().fold(tail, |cdr, car| LispEval::List(List::cons(car, cdr)))
+    }
+}
+
+impl From<Vec<LispEval>> for List {
+    fn from(items: Vec<LispEval>) -> Self {
+        items.into_iter().rev().fold(List::new(), |cdr, car| List::cons(car, LispEval::List(cdr)))
+    }
+}
+
+impl FromIterator<LispEval> for List {
+    fn from_iter<I: IntoIterator<Item = LispEval>>(iter: I) -> Self {
+        List::from(iter.into_iter().collect::<Vec<LispEval>>())
+    }
+}
+
+pub struct Iter<'a> {
+    next: Option<&'a Pair>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a LispEval;
+
+    fn next(&mut self) -> Option<&'a LispEval> {
+        let pair = self.next?;
+        self.next = match &pair.cdr {
+            LispEval::List(list) => list.0.as_deref(),
+            _ => None
+        };
+        Some(&pair.car)
+    }
+}
+
+impl<'a> IntoIterator for &'a List {
+    type Item = &'a LispEval;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+// Owning iterator, moves items out of cells which are not shared
+pub struct IntoIter {
+    next: List,
+}
+
+impl Iterator for IntoIter {
+    type Item = LispEval;
+
+    fn next(&mut self) -> Option<LispEval> {
+        let pair = self.next.0.take()?;
+        let (car, cdr) = match Rc::try_unwrap(pair) {
+            Ok(Pair { car, cdr }) => (car, cdr),
+            Err(pair) => (pair.car.clone(), pair.cdr.clone())
+        };
+        if let LispEval::List(list) = cdr {
+            self.next = list;
+        }
+        Some(car)
+    }
+}
+
+impl IntoIterator for List {
+    type Item = LispEval;
+    type IntoIter = IntoIter;
+
+    fn into_iter(self) -> IntoIter {
+        IntoIter {
+            next: self
+        }
+    }
+}
+
+// Long lists are dropped iteratively, recursive drops would overflow the stack
+impl Drop for List {
+    fn drop(&mut self) {
+        let mut next = self.0.take();
+        while let Some(pair) = next {
+            next = match Rc::try_unwrap(pair) {
+                Ok(mut pair) => match &mut pair.cdr {
+                    LispEval::List(list) => list.0.take(),
+                    _ => None
+                },
+                Err(_) => None
+            };
+        }
+    }
+}
+
+// Lists are compared and hashed iteratively for the same reason
+impl PartialEq for List {
+    fn eq(&self, other: &Self) -> bool {
+        let (mut a, mut b) = (self.0.as_deref(), other.0.as_deref());
+        loop {
+            match (a, b) {
+                (None, None) => return true,
+                (Some(x), Some(y)) => {
+                    if std::ptr::eq(x, y) {
+                        return true;
+                    }
+                    if x.car != y.car {
+                        return false;
+                    }
+                    match (&x.cdr, &y.cdr) {
+                        (LispEval::List(p), LispEval::List(q)) => {
+                            a = p.0.as_deref();
+                            b = q.0.as_deref();
+                        },
+                        (p, q) => return p == q,
+                    }
+                },
+                _ => return false,
+            }
+        }
+    }
+}
+
+impl Hash for List {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for item in self.iter() {
+            item.hash(state);
+        }
+        if let Some(tail) = self.improper_tail() {
+            tail.hash(state);
+        }
+    }
+}
+
+impl fmt::Display for List {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let items:Vec<String> = self.iter().map(
+            |item| item.to_string()
+        ).collect();
+        match self.improper_tail() {
+            Some(tail) => write!(f, "({} . {})", items.join(" "), tail),
+            None => write!(f, "({})", items.join(" ")),
+        }
+    }
+}
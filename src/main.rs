@@ -1,13 +1,17 @@
 #![allow(unused)]
 
+use std::io::{self, BufRead, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use rlisp::interpreter::{Interpreter, Engine};
+use rlisp::limits::Limits;
+use rlisp::eval::LispEval;
 use rlisp::exp::{tokenize, parse};
-use rlisp::env::default_env;
-use rlisp::eval::eval;
-
-use rlisp::utils::reader::{from_reader};
-
-use std::fs::File;
-use std::io::BufReader;
+use rlisp::utils::exceptions::{ParseError, EvalError, LispError};
 
 use clap::Parser;
 use log::{info, debug, error, LevelFilter};
@@ -16,9 +20,45 @@ use simple_logger::SimpleLogger;
 #[derive(Parser)]
 pub struct CLI {
 
-    /// The path to the file to read
+    /// The path to the file to read, starts a REPL when omitted
     #[clap(parse(from_os_str))]
-    path: std::path::PathBuf,
+    path: Option<std::path::PathBuf>,
+
+    /// Adds a directory searched for imported modules, before those in RLISP_PATH
+    #[clap(short = 'L', long = "lib-path", parse(from_os_str), multiple_occurrences(true))]
+    lib_path: Vec<std::path::PathBuf>,
+
+    /// Allows the file builtins to access the directory tree
+    #[clap(long = "allow-fs", parse(from_os_str))]
+    allow_fs: Option<std::path::PathBuf>,
+
+    /// Starts with the builtins only, without the prelude
+    #[clap(long = "bare")]
+    bare: bool,
+
+    /// Stops evaluation after this many evaluated expressions
+    #[clap(long = "fuel")]
+    fuel: Option<u64>,
+
+    /// Limits the depth of nested procedure calls
+    #[clap(long = "max-depth")]
+    max_depth: Option<usize>,
+
+    /// Limits the total size in bytes of the values built
+    #[clap(long = "max-memory")]
+    max_memory: Option<usize>,
+
+    /// Interrupts an evaluation running for longer than this many seconds
+    #[clap(long = "timeout")]
+    timeout: Option<f64>,
+
+    /// Evaluates by walking the expression tree or with the bytecode VM
+    #[clap(long = "engine", default_value = "tree", possible_values = ["tree", "vm"])]
+    engine: String,
+
+    /// Optimizes programs before running them, assuming builtins and inlined procedures are not redefined
+    #[clap(short = 'O')]
+    optimize: bool,
 
     /// Sets the logging level.
     #[clap(
@@ -37,9 +77,23 @@ fn parse_log_level(log_level: &str) -> Option<LevelFilter> {
     }
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+// Stack of the thread running the interpreter, every nested procedure call
+// uses some of it
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+type RunError = Box<dyn std::error::Error + Send + Sync>;
+
+fn main() -> Result<(), RunError> {
 
     let args = CLI::parse();
+    thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || run(args))?
+        .join()
+        .expect("interpreter thread panicked")
+}
+
+fn run(args: CLI) -> Result<(), RunError> {
 
     let parsed_log_level = parse_log_level(&args.log_level);
 
@@ -50,19 +104,151 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             .unwrap();
     }
 
-    let mut f = BufReader::new(File::open(&args.path).expect("open failed"));
-
-    let terms = from_reader(f).unwrap();
+    let mut interp = if args.bare { Interpreter::bare() } else { Interpreter::new() };
 
-    let mut env = default_env();
+    if args.engine == "vm" {
+        interp.set_engine(Engine::Vm);
+    }
+    interp.set_optimize(args.optimize);
+    interp.set_limits(Limits {
+        fuel: args.fuel,
+        max_depth: args.max_depth,
+        max_memory: args.max_memory,
+    });
+    if let Some(root) = &args.allow_fs {
+        interp.allow_fs(root)?;
+    }
+    for dir in &args.lib_path {
+        interp.add_search_path(dir);
+    }
+    if let Some(paths) = std::env::var_os("RLISP_PATH") {
+        for dir in std::env::split_paths(&paths) {
+            interp.add_search_path(dir);
+        }
+    }
 
-    for term in terms {
-        let tokens = tokenize(term);
-        let (exp, _) = parse(&tokens[..]).unwrap();
-        let value = eval(exp.clone(), &mut env).unwrap();
-    
-        debug!("{} -> {}", exp, value);
+    // Ctrl-C interrupts the running evaluation, a second one exits when the
+    // evaluation does not stop
+    let interrupt = interp.interrupt_handle();
+    ctrlc::set_handler(move || {
+        if interrupt.swap(true, Ordering::Relaxed) {
+            std::process::exit(130);
+        }
+    })?;
+    let timeout = match args.timeout {
+        Some(secs) => match Duration::try_from_secs_f64(secs) {
+            Ok(timeout) => Some(timeout),
+            Err(_) => {
+                eprintln!("error: invalid timeout {}, expected a number of seconds", secs);
+                std::process::exit(2);
+            },
+        },
+        None => None,
+    };
+
+    match &args.path {
+        Some(path) => {
+            let result = with_timeout(&interp.interrupt_handle(), timeout, || interp.eval_file(path));
+            if let Err(e) = result {
+                eprintln!("error: {}", e);
+                std::process::exit(1);
+            }
+        },
+        None => repl(&mut interp, timeout)?,
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+// Why an evaluation failed, telling a timeout apart from other interruptions
+enum Failure {
+    Error(LispError),
+    Timeout(Duration),
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Failure::Error(e) => write!(f, "{}", e),
+            Failure::Timeout(timeout) => write!(f, "timed out after {:?}", timeout),
+        }
+    }
+}
+
+// Runs an evaluation, interrupting it once the timeout elapsed
+fn with_timeout<F>(interrupt: &Arc<AtomicBool>, timeout: Option<Duration>, eval: F
+) -> Result<LispEval, Failure>
+where
+    F: FnOnce() -> Result<LispEval, LispError>
+{
+    interrupt.store(false, Ordering::Relaxed);
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return eval().map_err(Failure::Error),
+    };
+
+    // The watchdog stops waiting when the sender is dropped
+    let (done, finished) = mpsc::channel::<()>();
+    let watched = interrupt.clone();
+    let watchdog = thread::spawn(move || {
+        let expired = finished.recv_timeout(timeout) == Err(mpsc::RecvTimeoutError::Timeout);
+        if expired {
+            watched.store(true, Ordering::Relaxed);
+        }
+        expired
+    });
+
+    let result = eval();
+    drop(done);
+    let expired = watchdog.join().unwrap_or(false);
+    match result {
+        Err(LispError::Eval(EvalError::Interrupted)) if expired => Err(Failure::Timeout(timeout)),
+        result => result.map_err(Failure::Error),
+    }
+}
+
+// Checks if the input ends in the middle of an expression
+fn is_incomplete(input: &str) -> bool {
+    let tokens = tokenize(input.to_string());
+    let mut rest = &tokens[..];
+    while !rest.is_empty() {
+        match parse(rest) {
+            Ok((_, next)) => rest = next,
+            Err(e) => return e == ParseError::MissingToken,
+        }
+    }
+    false
+}
+
+// Reads expressions from stdin and prints their values, void results are
+// not printed. Input spanning several lines is read until it is complete.
+// Each input gets the full limits and timeout, interrupting it keeps the
+// definitions made so far.
+fn repl(interp: &mut Interpreter, timeout: Option<Duration>) -> io::Result<()> {
+    let interrupt = interp.interrupt_handle();
+    let stdin = io::stdin();
+    let mut input = String::new();
+
+    loop {
+        print!("{}", if input.is_empty() { "rlisp> " } else { "...    " });
+        io::stdout().flush()?;
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            println!();
+            return Ok(());
+        }
+        input.push_str(&line);
+        if is_incomplete(&input) {
+            continue;
+        }
+
+        interp.reset_usage();
+        match with_timeout(&interrupt, timeout, || interp.eval_str(&input)) {
+            Ok(LispEval::Void) => {},
+            Ok(value) => println!("{}", value),
+            Err(e) => eprintln!("error: {}", e),
+        }
+        input.clear();
+    }
+}
@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+
+use crate::exp::{LispExp, tokenize, parse};
+use crate::env::{LispEnv, default_env, bare_env};
+use crate::eval::{LispEval, eval_exp};
+use crate::interpreter::Interpreter;
+use crate::symbol::Symbol;
+
+use crate::utils::exceptions::{EvalError, ParseError};
+
+// Extension of rlisp source files, modules are found as <name>.rl
+const EXTENSION: &str = "rl";
+
+// The exported bindings of a loaded module
+pub(crate) struct Module {
+    exports: Vec<(Symbol, LispEval)>,
+}
+
+// Module state of an interpreter. Every module is evaluated in its own global
+// environment identified by a number, 0 being the main program. The active one
+// lives in `Interpreter::env`, the others are parked here.
+#[derive(Default)]
+pub(crate) struct Modules {
+    pub(crate) search_path: Vec<PathBuf>,
+    pub(crate) current: usize,
+    pub(crate) parked: HashMap<usize, LispEnv>,
+    pub(crate) current_file: Option<PathBuf>,
+    // Directory of the file run by Interpreter::eval_file, while it runs
+    pub(crate) root: Option<PathBuf>,
+    pub(crate) bare: bool,
+    count: usize,
+    cache: HashMap<PathBuf, Rc<Module>>,
+    loading: Vec<PathBuf>,
+    declaration: Option<(Symbol, Vec<Symbol>)>,
+    in_module: bool,
+}
+
+fn load_error(path: &Path, message: String) -> EvalError {
+    EvalError::LoadError(format!("{}: {}", path.display(), message))
+}
+
+// Describes a syntax error without the tokens of the file
+fn syntax_error(e: &ParseError) -> &'static str {
+    match e {
+        ParseError::EmptyInput() => "empty input",
+        ParseError::UnexpectedToken(_) => "unexpected token",
+        ParseError::UnexpectedExpression() => "unexpected expression",
+        ParseError::MissingToken => "missing token",
+        ParseError::UnterminatedString() => "unterminated string",
+    }
+}
+
+fn symbol_name(exp: &LispExp) -> Result<Symbol, EvalError> {
+    match exp {
+        LispExp::Symbol(s) => Ok(*s),
+        _ => Err(EvalError::InvalidModule(exp.to_string()))
+    }
+}
+
+// Directory used to resolve relative paths, the one of the file being evaluated
+fn base_dir(interp: &Interpreter) -> PathBuf {
+    interp.modules.current_file.as_ref()
+        .and_then(|file| file.parent())
+        .map(Path::to_path_buf)
+        .unwrap_or_default()
+}
+
+// Files read by load and import must be inside the directory of the file run
+// by Interpreter::eval_file, a directory of the search path or the directory
+// given to Interpreter::allow_fs. Returns the canonical path of the file.
+fn check_access(path: &Path, interp: &Interpreter) -> Result<PathBuf, EvalError> {
+    let roots: Vec<PathBuf> = interp.modules.root.iter()
+        .chain(&interp.modules.search_path)
+        .chain(&interp.fs_root)
+        .filter_map(|dir| dir.canonicalize().ok())
+        .collect();
+    let inside = |path: &Path| roots.iter().any(|root| path.starts_with(root));
+
+    match path.canonicalize() {
+        Ok(full) if inside(&full) => Ok(full),
+        Err(e) if inside(path) && !path.components().any(|c| c == Component::ParentDir) => {
+            Err(load_error(path, e.to_string()))
+        },
+        _ => Err(EvalError::PermissionDenied(path.display().to_string()))
+    }
+}
+
+// Runs the body with the file as the current one, failing if the file is
+// already being evaluated
+pub(crate) fn in_file<T, F>(path: &Path, interp: &mut Interpreter, body: F
+) -> Result<T, EvalError>
+where
+    F: FnOnce(&mut Interpreter) -> T
+{
+    if interp.modules.loading.iter().any(|p| p == path) {
+        return Err(EvalError::LoadCycle(path.display().to_string()));
+    }
+    let previous = interp.modules.current_file.replace(path.to_path_buf());
+    interp.modules.loading.push(path.to_path_buf());
+    let result = body(interp);
+    interp.modules.loading.pop();
+    interp.modules.current_file = previous;
+    Ok(result)
+}
+
+// Evaluates the forms of a file in the active global environment
+fn eval_file(path: &Path, interp: &mut Interpreter) -> Result<LispEval, EvalError> {
+    let path = check_access(path, interp)?;
+    let source = fs::read_to_string(&path).map_err(
+        |e| load_error(&path, e.to_string())
+    )?;
+    in_file(&path, interp, |interp| eval_source(&path, &source, interp))?
+}
+
+fn eval_source(path: &Path, source: &str, interp: &mut Interpreter
+) -> Result<LispEval, EvalError> {
+    let tokens = tokenize(source.to_string());
+    let mut rest = &tokens[..];
+
+    let mut program = Vec::new();
+    while !rest.is_empty() {
+        let (exp, next) = parse(rest).map_err(
+            |e| load_error(path, syntax_error(&e).to_string())
+        )?;
+        program.push(exp);
+        rest = next;
+    }
+    interp.eval_program(program)
+}
+
+// (load "file.rl") evaluates a file in the current environment, relative
+// paths are resolved from the directory of the current file
+pub(crate) fn load(args: &[LispExp], interp: &mut Interpreter
+) -> Result<LispEval, EvalError> {
+
+    if args.len() != 1 {
+        return Err(EvalError::InvalidNumberOfArguments());
+    }
+    let path = match eval_exp(&args[0], interp)? {
+        LispEval::Str(path) => base_dir(interp).join(path),
+        _ => return Err(EvalError::InvalidAgrumentType())
+    };
+    // Locals of the calling procedure are not visible to the loaded file
+    let scope = interp.scope.take();
+    let result = eval_file(&path, interp);
+    interp.scope = scope;
+    result
+}
+
+// (module name (export symbol...)) declares the module defined by the file
+pub(crate) fn declare(args: &[LispExp], interp: &mut Interpreter
+) -> Result<LispEval, EvalError> {
+
+    let (name, exports) = match args {
+        [name, LispExp::List(exports)] => (symbol_name(name)?, &exports[..]),
+        [name] => (symbol_name(name)?, &[][..]),
+        _ => return Err(EvalError::InvalidModule("(module name (export ...))".to_string()))
+    };
+    let exports = match exports.split_first() {
+        Some((LispExp::Symbol(Symbol::EXPORT), names)) => {
+            names.iter().map(symbol_name).collect::<Result<Vec<Symbol>, EvalError>>()?
+        },
+        None => Vec::new(),
+        _ => return Err(EvalError::InvalidModule("(module name (export ...))".to_string()))
+    };
+
+    if !interp.modules.in_module || interp.modules.declaration.is_some() {
+        return Err(EvalError::InvalidModule(name.to_string()));
+    }
+    interp.modules.declaration = Some((name, exports));
+    Ok(LispEval::Void)
+}
+
+// (import name symbol...) loads the module once and binds its exports as
+// name/symbol, the listed symbols are also bound without the prefix
+pub(crate) fn import(args: &[LispExp], interp: &mut Interpreter
+) -> Result<LispEval, EvalError> {
+
+    let (name, names) = args.split_first()
+        .ok_or(
+            EvalError::InvalidNumberOfArguments()
+        )?;
+    let name = symbol_name(name)?;
+    let module = load_module(name.name(), interp)?;
+
+    for (export, value) in &module.exports {
+        interp.define(Symbol::intern(&format!("{}/{}", name, export)), value.clone());
+    }
+    for unqualified in names {
+        let unqualified = symbol_name(unqualified)?;
+        let (_, value) = module.exports.iter().find(|(export, _)| *export == unqualified)
+            .ok_or(
+                EvalError::UnknownSymbol(format!("{}/{}", name, unqualified))
+            )?;
+        interp.define(unqualified, value.clone());
+    }
+    Ok(LispEval::Void)
+}
+
+// Finds <name>.rl next to the current file, then in the search path
+fn find_module(name: &str, interp: &Interpreter) -> Result<PathBuf, EvalError> {
+    let file = Path::new(name).with_extension(EXTENSION);
+    let path = std::iter::once(base_dir(interp))
+        .chain(interp.modules.search_path.iter().cloned())
+        .map(|dir| dir.join(&file))
+        .find(|path| path.is_file())
+        .ok_or(
+            EvalError::ModuleNotFound(name.to_string())
+        )?;
+    check_access(&path, interp)
+}
+
+fn load_module(name: &str, interp: &mut Interpreter) -> Result<Rc<Module>, EvalError> {
+    let path = find_module(name, interp)?;
+    if let Some(module) = interp.modules.cache.get(&path) {
+        return Ok(module.clone());
+    }
+
+    interp.modules.count += 1;
+    let id = interp.modules.count;
+    let env = if interp.modules.bare { bare_env() } else { default_env() };
+    interp.modules.parked.insert(id, env);
+
+    let globals = interp.switch_globals(id);
+    let scope = interp.scope.take();
+    let in_module = std::mem::replace(&mut interp.modules.in_module, true);
+    let declaration = interp.modules.declaration.take();
+
+    let result = eval_file(&path, interp);
+
+    let declared = std::mem::replace(&mut interp.modules.declaration, declaration);
+    interp.modules.in_module = in_module;
+    interp.scope = scope;
+    interp.switch_globals(globals);
+    result?;
+
+    let exports = match declared {
+        Some((declared, exports)) if declared.name() == name => exports,
+        _ => return Err(EvalError::InvalidModule(name.to_string()))
+    };
+    let env = &interp.modules.parked[&id];
+    let exports = exports.into_iter().map(|export| {
+        match env.get(export) {
+            Some(value) => Ok((export, value.clone())),
+            None => Err(EvalError::UnknownSymbol(format!("{}/{}", name, export)))
+        }
+    }).collect::<Result<Vec<(Symbol, LispEval)>, EvalError>>()?;
+
+    let module = Rc::new(Module { exports });
+    interp.modules.cache.insert(path, module.clone());
+    Ok(module)
+}
@@ -0,0 +1,77 @@
+use std::fmt;
+use std::rc::Rc;
+
+use crate::eval::LispEval;
+use crate::interpreter::Interpreter;
+
+use crate::utils::exceptions::EvalError;
+
+pub type NativeBody = dyn Fn(&mut Interpreter, &[LispEval]) -> Result<LispEval, EvalError>;
+
+// Number of arguments accepted by a native function
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arity {
+    Exact(usize),
+    AtLeast(usize),
+    Range(usize, usize),
+}
+
+impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match *self {
+            Arity::Exact(n) => count == n,
+            Arity::AtLeast(min) => count >= min,
+            Arity::Range(min, max) => count >= min && count <= max,
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Arity::Exact(n) => write!(f, "{}", n),
+            Arity::AtLeast(min) => write!(f, "at least {}", min),
+            Arity::Range(min, max) => write!(f, "{} to {}", min, max),
+        }
+    }
+}
+
+// A builtin implemented in Rust. The body receives the running interpreter,
+// so it can call back into Lisp procedures, and may capture Rust state.
+#[derive(Clone)]
+pub struct NativeFn {
+    pub name: String,
+    pub arity: Arity,
+    pub doc: String,
+    body: Rc<NativeBody>,
+}
+
+impl NativeFn {
+
+    pub fn new<F>(name: &str, arity: Arity, doc: &str, body: F) -> Self
+    where
+        F: Fn(&mut Interpreter, &[LispEval]) -> Result<LispEval, EvalError> + 'static
+    {
+        NativeFn {
+            name: name.to_string(),
+            arity,
+            doc: doc.to_string(),
+            body: Rc::new(body),
+        }
+    }
+
+    pub fn call(&self, interp: &mut Interpreter, args: &[LispEval]
+    ) -> Result<LispEval, EvalError> {
+
+        if !self.arity.accepts(args.len()) {
+            return Err(EvalError::ArityMismatch(self.name.clone(), self.arity, args.len()));
+        }
+        (self.body)(interp, args)
+    }
+}
+
+impl fmt::Debug for NativeFn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<native {}>", self.name)
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::HashMap;
+
+use crate::exp::LispExp;
+use crate::eval::{LispEval, binding_name};
+use crate::interpreter::Interpreter;
+use crate::resolver::{Definitions, frame_names};
+use crate::symbol::Symbol;
+
+// Builtins without effects, their calls with constant arguments are computed
+// once before the program runs
+const PURE: [&str; 18] = [
+    "not", "and", "or", "+", "-", "*", "/", "%", "=", "<", ">", "<=", ">=",
+    "bool?", "number?", "integer?", "string?", "eq?",
+];
+
+const CONSTANTS: [(&str, f64); 2] = [
+    ("pi", core::f64::consts::PI),
+    ("e", core::f64::consts::E),
+];
+
+// Number of expressions in the body of procedures that are inlined
+const INLINE_SIZE: usize = 16;
+
+// A procedure whose calls are replaced by its body
+struct Inline {
+    params: Vec<Symbol>,
+    body: LispExp,
+}
+
+// Rewrites a program into one giving the same results with less work: calls
+// of pure builtins with constant arguments are computed, if forms with a
+// constant test are replaced by the branch taken, and calls of small
+// procedures defined at the top of the program are replaced by their body.
+//
+// The builtins and the procedures inlined are assumed not to be redefined by
+// later programs. Programs loading files, which can redefine anything, are
+// left as they are.
+pub(crate) fn optimize(program: Vec<LispExp>, interp: &mut Interpreter) -> Vec<LispExp> {
+    let definitions = Definitions::collect(&program, interp);
+    if !definitions.checked {
+        return program;
+    }
+    let mut optimizer = Optimizer {
+        interp,
+        definitions,
+        frames: Vec::new(),
+        inlines: HashMap::new(),
+        inlining: Vec::new(),
+    };
+    program.iter().map(|exp| {
+        let exp = optimizer.exp(exp);
+        optimizer.add_inline(&exp);
+        exp
+    }).collect()
+}
+
+struct Optimizer<'a> {
+    interp: &'a mut Interpreter,
+    definitions: Definitions,
+    // Variables of the procedures being optimized, the innermost last
+    frames: Vec<Vec<Symbol>>,
+    inlines: HashMap<Symbol, Inline>,
+    // Procedures being inlined, they are not inlined again in their own body
+    inlining: Vec<Symbol>,
+}
+
+fn is_constant(exp: &LispExp) -> bool {
+    matches!(exp, LispExp::Bool(_) | LispExp::Number(_) | LispExp::Str(_))
+}
+
+fn is_special_form(head: Symbol) -> bool {
+    matches!(head,
+        Symbol::QUOTE | Symbol::LOAD | Symbol::MODULE | Symbol::IMPORT | Symbol::EXPORT
+        | Symbol::IF | Symbol::BEGIN | Symbol::DEFINE | Symbol::LAMBDA | Symbol::FN
+        | Symbol::DEFMACRO | Symbol::WHILE | Symbol::DO | Symbol::DOTIMES | Symbol::DOLIST)
+}
+
+fn size(exp: &LispExp) -> usize {
+    match exp {
+        LispExp::List(items) => 1 + items.iter().map(size).sum::<usize>(),
+        _ => 1
+    }
+}
+
+fn mentions(exp: &LispExp, name: Symbol) -> bool {
+    match exp {
+        LispExp::Symbol(s) => *s == name,
+        LispExp::List(items) => items.iter().any(|item| mentions(item, name)),
+        _ => false
+    }
+}
+
+// Replaces the parameters in a body by the arguments
+fn substitute(exp: &LispExp, bindings: &HashMap<Symbol, &LispExp>) -> LispExp {
+    match exp {
+        LispExp::Symbol(s) => bindings.get(s).map_or_else(|| exp.clone(), |arg| (*arg).clone()),
+        LispExp::List(items) => match items.first() {
+            Some(LispExp::Symbol(Symbol::QUOTE)) => exp.clone(),
+            _ => LispExp::from(items.iter().map(|item| substitute(item, bindings)).collect::<Vec<_>>())
+        },
+        _ => exp.clone()
+    }
+}
+
+impl<'a> Optimizer<'a> {
+
+    fn is_local(&self, name: Symbol) -> bool {
+        self.frames.iter().any(|frame| frame.contains(&name))
+    }
+
+    // Whether a name is bound to the builtin of the same name wherever it is
+    // used in the program
+    fn is_builtin(&self, name: Symbol) -> bool {
+        !self.is_local(name) && !self.definitions.contains(name)
+    }
+
+    fn constant(&self, name: Symbol) -> Option<LispExp> {
+        let (_, value) = CONSTANTS.iter().find(|(constant, _)| *constant == name.name())?;
+        match self.interp.env.get(name) {
+            Some(LispEval::Number(n)) if n == value && self.is_builtin(name) => Some(LispExp::Number(*n)),
+            _ => None
+        }
+    }
+
+    fn exp(&mut self, exp: &LispExp) -> LispExp {
+        match exp {
+            LispExp::Symbol(s) => self.constant(*s).unwrap_or_else(|| exp.clone()),
+            LispExp::List(items) => self.form(items).unwrap_or_else(|| exp.clone()),
+            _ => exp.clone()
+        }
+    }
+
+    fn exps(&mut self, exps: &[LispExp]) -> Vec<LispExp> {
+        exps.iter().map(|exp| self.exp(exp)).collect()
+    }
+
+    // The optimized form, or none when it is left as it is
+    fn form(&mut self, items: &[LispExp]) -> Option<LispExp> {
+        let (head, args) = match items.split_first() {
+            Some((LispExp::Symbol(head), args)) => (*head, args),
+            _ => return None
+        };
+        let mut form = vec![LispExp::Symbol(head)];
+        match (head, args) {
+            (Symbol::IF, [test, then, otherwise]) => {
+                return match self.exp(test) {
+                    LispExp::Bool(true) => Some(self.exp(then)),
+                    LispExp::Bool(false) => Some(self.exp(otherwise)),
+                    test => Some(LispExp::from(vec![LispExp::Symbol(head), test, self.exp(then), self.exp(otherwise)]))
+                };
+            },
+            (Symbol::DEFINE, [name, value]) => {
+                form.push(name.clone());
+                form.push(self.exp(value));
+            },
+            (Symbol::FN, [name, rest @ ..]) if rest.len() >= 2 => {
+                form.push(name.clone());
+                form.extend(self.procedure(rest));
+            },
+            (Symbol::LAMBDA, args) if args.len() >= 2 => form.extend(self.procedure(args)),
+            (Symbol::DO, [LispExp::List(specs), LispExp::List(clause), body @ ..]) => {
+                let specs: Vec<LispExp> = specs.iter().map(|spec| self.loop_header(spec)).collect();
+                form.push(LispExp::from(specs));
+                let clause = self.exps(clause);
+                form.push(LispExp::from(clause));
+                form.extend(self.exps(body));
+            },
+            (Symbol::DOTIMES | Symbol::DOLIST, [header, body @ ..]) => {
+                form.push(self.loop_header(header));
+                form.extend(self.exps(body));
+            },
+            (Symbol::BEGIN | Symbol::WHILE, _) => form.extend(self.exps(args)),
+            _ if is_special_form(head) => return None,
+            _ if !self.is_local(head) && self.definitions.is_macro(head, self.interp) => return None,
+            _ => {
+                let args = self.exps(args);
+                return Some(self.fold(head, &args)
+                    .or_else(|| self.inline(head, &args))
+                    .unwrap_or_else(|| {
+                        form.extend(args);
+                        LispExp::from(form)
+                    }));
+            }
+        }
+        Some(LispExp::from(form))
+    }
+
+    // The value of a pure builtin called with constant arguments, when it is
+    // a constant as well. Calls failing are left to fail when evaluated.
+    fn fold(&mut self, head: Symbol, args: &[LispExp]) -> Option<LispExp> {
+        if !PURE.contains(&head.name()) || !self.is_builtin(head) || !args.iter().all(is_constant) {
+            return None;
+        }
+        let func = match self.interp.env.get(head) {
+            Some(LispEval::Func(func)) if func.name == head.name() => func.clone(),
+            _ => return None
+        };
+        let args: Vec<LispEval> = args.iter().map(|arg| match arg {
+            LispExp::Bool(b) => LispEval::Bool(*b),
+            LispExp::Number(n) => LispEval::Number(*n),
+            LispExp::Str(s) => LispEval::Str(s.clone()),
+            _ => unreachable!()
+        }).collect();
+        match func.call(self.interp, &args) {
+            Ok(LispEval::Bool(b)) => Some(LispExp::Bool(b)),
+            Ok(LispEval::Number(n)) => Some(LispExp::Number(n)),
+            Ok(LispEval::Str(s)) => Some(LispExp::Str(s)),
+            _ => None
+        }
+    }
+
+    // The body of an inlined procedure with the arguments in place of its
+    // parameters. Arguments are only substituted when evaluating them more
+    // than once or not at all makes no difference: constants and variables.
+    fn inline(&mut self, head: Symbol, args: &[LispExp]) -> Option<LispExp> {
+        if self.is_local(head) || self.inlining.contains(&head) {
+            return None;
+        }
+        let inline = self.inlines.get(&head)?;
+        let simple = |arg: &LispExp| is_constant(arg) || matches!(arg, LispExp::Symbol(_));
+        if inline.params.len() != args.len() || !args.iter().all(simple) {
+            return None;
+        }
+        // Variables of the body must not be captured by the variables at the
+        // place of the call
+        if !self.frames.is_empty() && self.captures(&inline.body, &inline.params) {
+            return None;
+        }
+        let bindings = inline.params.iter().copied().zip(args).collect();
+        let body = substitute(&inline.body, &bindings);
+
+        self.inlining.push(head);
+        let body = self.exp(&body);
+        self.inlining.pop();
+        Some(body)
+    }
+
+    fn captures(&self, exp: &LispExp, params: &[Symbol]) -> bool {
+        match exp {
+            LispExp::Symbol(s) => !params.contains(s)
+                && (self.is_local(*s) || self.definitions.macro_args.contains(s)),
+            LispExp::List(items) => match items.first() {
+                Some(LispExp::Symbol(Symbol::QUOTE)) => false,
+                _ => items.iter().any(|item| self.captures(item, params))
+            },
+            _ => false
+        }
+    }
+
+    // Remembers a procedure defined at the top of the program to inline its
+    // later calls. It must be defined once, with fixed parameters, and its
+    // body must be small and only made of calls, if forms and constants.
+    fn add_inline(&mut self, exp: &LispExp) {
+        let (name, params, body) = match exp {
+            LispExp::List(items) => match &items[..] {
+                [LispExp::Symbol(Symbol::FN), LispExp::Symbol(name), rest @ ..] if rest.len() >= 2 => {
+                    let (body, params) = rest.split_last().unwrap();
+                    (*name, params, body)
+                },
+                _ => return
+            },
+            _ => return
+        };
+        let params: Option<Vec<Symbol>> = params.iter().map(|param| match param {
+            LispExp::Symbol(s) if *s != Symbol::REST => Some(*s),
+            _ => None
+        }).collect();
+        let params = match params {
+            Some(params) => params,
+            None => return
+        };
+        let distinct = params.iter().enumerate().all(|(i, param)| !params[..i].contains(param));
+        if !distinct || self.definitions.names.get(&name) != Some(&1)
+            || size(body) > INLINE_SIZE || mentions(body, name) || !self.is_simple(body) {
+            return;
+        }
+        self.inlines.insert(name, Inline { params, body: body.clone() });
+    }
+
+    fn is_simple(&self, exp: &LispExp) -> bool {
+        let items = match exp {
+            LispExp::List(items) => items,
+            _ => return true
+        };
+        match items.split_first() {
+            Some((LispExp::Symbol(Symbol::QUOTE), [_])) => true,
+            Some((LispExp::Symbol(Symbol::IF), args)) => args.len() == 3 && args.iter().all(|arg| self.is_simple(arg)),
+            Some((LispExp::Symbol(head), args)) => {
+                !is_special_form(*head) && !self.definitions.is_macro(*head, self.interp)
+                    && args.iter().all(|arg| self.is_simple(arg))
+            },
+            _ => false
+        }
+    }
+
+    // (var expressions...) where the variable is bound by name
+    fn loop_header(&mut self, header: &LispExp) -> LispExp {
+        match header {
+            LispExp::List(items) if matches!(items.first(), Some(LispExp::Symbol(_))) => {
+                let mut optimized = vec![items[0].clone()];
+                optimized.extend(self.exps(&items[1..]));
+                LispExp::from(optimized)
+            },
+            _ => header.clone()
+        }
+    }
+
+    // The parameters and the optimized body of a procedure
+    fn procedure(&mut self, args: &[LispExp]) -> Vec<LispExp> {
+        let (body, params) = args.split_last().unwrap();
+        let mut frame: Vec<Symbol> = params.iter().map(binding_name).collect();
+        frame_names(body, &mut frame);
+
+        self.frames.push(frame);
+        let body = self.exp(body);
+        self.frames.pop();
+
+        let mut procedure = params.to_vec();
+        procedure.push(body);
+        procedure
+    }
+}
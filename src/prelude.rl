@@ -0,0 +1,84 @@
+; The rlisp prelude, evaluated into the default environment.
+
+; Control macros
+
+(defmacro when condition &rest body
+  (list 'if condition (cons 'begin body) (list 'void)))
+
+(defmacro unless condition &rest body
+  (list 'if condition (list 'void) (cons 'begin body)))
+
+(fn %cond-clauses clauses
+  (if (null? clauses)
+      (list 'void)
+      (if (eq? (caar clauses) 'else)
+          (cons 'begin (cdar clauses))
+          (list 'if (caar clauses)
+                (cons 'begin (cdar clauses))
+                (%cond-clauses (cdr clauses))))))
+
+; (cond (test body...)... (else body...))
+(defmacro cond &rest clauses
+  (%cond-clauses clauses))
+
+; (let ((name value)...) body...)
+(defmacro let bindings &rest body
+  (if (null? bindings)
+      (cons 'begin body)
+      (list 'apply
+            (append (list 'lambda) (map car bindings) (list (cons 'begin body)))
+            (cons 'list (map cadr bindings)))))
+
+; Math helpers
+
+(fn inc x (+ x 1))
+(fn dec x (- x 1))
+(fn abs x (if (< x 0) (- 0 x) x))
+(fn square x (* x x))
+(fn cube x (* x x x))
+
+(fn zero? x (= x 0))
+(fn positive? x (> x 0))
+(fn negative? x (< x 0))
+(fn even? x (= (% x 2) 0))
+(fn odd? x (not (even? x)))
+
+(fn max x &rest xs (fold-left (lambda a b (if (> a b) a b)) x xs))
+(fn min x &rest xs (fold-left (lambda a b (if (< a b) a b)) x xs))
+(fn clamp x low high (max low (min x high)))
+
+(fn gcd a b (if (= b 0) (abs a) (gcd b (% a b))))
+(fn lcm a b (if (= (* a b) 0) 0 (abs (/ (* a b) (gcd a b)))))
+
+; Power with a non-negative whole exponent
+(fn expt base n (if (= n 0) 1 (* base (expt base (- n 1)))))
+
+; List utilities
+
+(fn identity x x)
+(fn compose f g (lambda &rest xs (f (apply g xs))))
+
+(fn empty? xs (= (length xs) 0))
+(fn sum xs (fold-left + 0 xs))
+(fn product xs (fold-left * 1 xs))
+(fn maximum xs (reduce max xs))
+(fn minimum xs (reduce min xs))
+
+(fn remove pred xs (filter (lambda x (not (pred x))) xs))
+(fn partition pred xs (list (filter pred xs) (remove pred xs)))
+
+(fn take-while pred xs
+  (if (null? xs)
+      xs
+      (if (pred (car xs))
+          (cons (car xs) (take-while pred (cdr xs)))
+          '())))
+
+(fn drop-while pred xs
+  (if (null? xs)
+      xs
+      (if (pred (car xs))
+          (drop-while pred (cdr xs))
+          xs)))
+
+(fn repeat x n (map (lambda i x) (range n)))
@@ -0,0 +1,383 @@
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::exp::LispExp;
+use crate::eval::{LispEval, binding_name};
+use crate::interpreter::Interpreter;
+use crate::symbol::Symbol;
+use crate::vm::Param;
+
+use crate::utils::exceptions::EvalError;
+
+// A variable of the procedure `depth` frames up from the innermost one. While
+// its slot is not set, before it is defined in the procedure or once a loop
+// binding it is done, the variable of the enclosing procedures or the global
+// with the name is used. Parameters are always set, calls bind all of them.
+#[derive(Debug, Clone, Copy)]
+pub struct Local {
+    pub(crate) depth: u32,
+    pub(crate) slot: u32,
+    pub(crate) name: Symbol,
+}
+
+// A lambda with the layout of its frame: a slot for every parameter and for
+// every variable its body defines or binds in a loop
+#[derive(Debug)]
+pub struct Procedure {
+    pub(crate) params: Rc<[LispExp]>,
+    pub(crate) bindings: Vec<Param>,
+    pub(crate) slots: Rc<[Symbol]>,
+    pub(crate) body: LispExp,
+}
+
+// Resolves the variables of procedures in a program to the slots of their
+// frames, and fails with the first variable that can never be bound: neither
+// a global nor defined by the program. Procedures called by name may be
+// defined later, they are not checked. Arguments of macros are left as they
+// are, they are resolved by name once expanded.
+pub(crate) fn resolve(program: &[LispExp], interp: &Interpreter
+) -> Result<Vec<LispExp>, EvalError> {
+
+    let mut resolver = Resolver::new(interp, Definitions::collect(program, interp));
+    let program = program.iter().map(|exp| resolver.exp(exp)).collect();
+    match resolver.unbound {
+        Some(name) => Err(EvalError::UnknownSymbol(name.to_string())),
+        None => Ok(program)
+    }
+}
+
+// Resolves a lambda created while running, by a macro expansion for instance.
+// The variables of the enclosing procedures are looked up by name.
+pub(crate) fn procedure(params: &[LispExp], body: &LispExp, interp: &Interpreter) -> Procedure {
+    let definitions = Definitions { checked: false, ..Definitions::default() };
+    Resolver::new(interp, definitions).procedure(params, body)
+}
+
+// The names a program defines anywhere, with how many times they are defined
+#[derive(Default)]
+pub(crate) struct Definitions {
+    pub(crate) names: HashMap<Symbol, usize>,
+    pub(crate) macros: HashSet<Symbol>,
+    // Symbols passed to macros, their expansions may define them
+    pub(crate) macro_args: HashSet<Symbol>,
+    // Modules imported by the program, their exports are bound as module/name
+    pub(crate) modules: Vec<Symbol>,
+    // Loaded files can define anything, programs loading them are not checked
+    pub(crate) checked: bool,
+}
+
+impl Definitions {
+
+    pub(crate) fn collect(program: &[LispExp], interp: &Interpreter) -> Self {
+        let mut definitions = Definitions { checked: true, ..Definitions::default() };
+        for exp in program {
+            definitions.exp(exp, interp);
+        }
+        definitions
+    }
+
+    pub(crate) fn contains(&self, name: Symbol) -> bool {
+        self.names.contains_key(&name)
+    }
+
+    pub(crate) fn is_macro(&self, name: Symbol, interp: &Interpreter) -> bool {
+        self.macros.contains(&name) || matches!(interp.env.get(name), Some(LispEval::Macro(_)))
+    }
+
+    fn define(&mut self, name: Symbol) {
+        *self.names.entry(name).or_insert(0) += 1;
+    }
+
+    fn exp(&mut self, exp: &LispExp, interp: &Interpreter) {
+        let items = match exp {
+            LispExp::List(items) => items,
+            _ => return
+        };
+        match items.split_first() {
+            Some((LispExp::Symbol(Symbol::QUOTE), _)) => return,
+            Some((LispExp::Symbol(Symbol::LOAD), _)) => self.checked = false,
+            Some((LispExp::Symbol(Symbol::IMPORT), [LispExp::Symbol(module), names @ ..])) => {
+                self.modules.push(*module);
+                names.iter().for_each(|name| self.define(binding_name(name)));
+                return;
+            },
+            Some((LispExp::Symbol(head @ (Symbol::DEFINE | Symbol::FN | Symbol::DEFMACRO)), [name, ..])) => {
+                self.define(binding_name(name));
+                if *head == Symbol::DEFMACRO {
+                    self.macros.insert(binding_name(name));
+                    items[2..].iter().for_each(|item| self.templates(item));
+                }
+            },
+            Some((LispExp::Symbol(Symbol::DOTIMES | Symbol::DOLIST), [header, ..])) => {
+                if let Some(var) = loop_var(header) {
+                    self.define(var);
+                }
+            },
+            Some((LispExp::Symbol(Symbol::DO), [LispExp::List(specs), ..])) => {
+                specs.iter().filter_map(loop_var).for_each(|var| self.define(var));
+            },
+            Some((LispExp::Symbol(head), args)) if self.is_macro(*head, interp) => {
+                args.iter().for_each(|arg| self.symbols(arg));
+                return;
+            },
+            _ => {}
+        }
+        items.iter().for_each(|item| self.exp(item, interp));
+    }
+
+    // Symbols quoted in a macro body, its expansions may define them
+    fn templates(&mut self, exp: &LispExp) {
+        if let LispExp::List(items) = exp {
+            match items.split_first() {
+                Some((LispExp::Symbol(Symbol::QUOTE), quoted)) => {
+                    quoted.iter().for_each(|item| self.symbols(item));
+                },
+                _ => items.iter().for_each(|item| self.templates(item)),
+            }
+        }
+    }
+
+    fn symbols(&mut self, exp: &LispExp) {
+        match exp {
+            LispExp::Symbol(s) => {
+                self.define(*s);
+                self.macro_args.insert(*s);
+            },
+            LispExp::List(items) => items.iter().for_each(|item| self.symbols(item)),
+            _ => {}
+        }
+    }
+}
+
+struct Resolver<'a> {
+    interp: &'a Interpreter,
+    // Slot names of the procedures being resolved, the innermost last
+    frames: Vec<Vec<Symbol>>,
+    definitions: Definitions,
+    unbound: Option<Symbol>,
+}
+
+fn slot_of(slots: &mut Vec<Symbol>, name: Symbol) -> u32 {
+    match slots.iter().position(|slot| *slot == name) {
+        Some(slot) => slot as u32,
+        None => {
+            slots.push(name);
+            (slots.len() - 1) as u32
+        }
+    }
+}
+
+// The variable bound by a loop header like (var ...)
+pub(crate) fn loop_var(header: &LispExp) -> Option<Symbol> {
+    match header {
+        LispExp::List(items) => match items.first() {
+            Some(LispExp::Symbol(var)) => Some(*var),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+// Variables a procedure body binds in its own frame, outside of the
+// procedures it creates
+pub(crate) fn frame_names(exp: &LispExp, slots: &mut Vec<Symbol>) {
+    let items = match exp {
+        LispExp::List(items) => items,
+        _ => return
+    };
+    match items.split_first() {
+        Some((LispExp::Symbol(Symbol::QUOTE | Symbol::LAMBDA), _)) => return,
+        Some((LispExp::Symbol(Symbol::FN | Symbol::DEFMACRO), args)) => {
+            if let Some(name) = args.first() {
+                slot_of(slots, binding_name(name));
+            }
+            return;
+        },
+        Some((LispExp::Symbol(Symbol::DEFINE), [name, ..])) => {
+            slot_of(slots, binding_name(name));
+        },
+        Some((LispExp::Symbol(Symbol::DOTIMES | Symbol::DOLIST), [header, ..])) => {
+            if let Some(var) = loop_var(header) {
+                slot_of(slots, var);
+            }
+        },
+        Some((LispExp::Symbol(Symbol::DO), [LispExp::List(specs), ..])) => {
+            for var in specs.iter().filter_map(loop_var) {
+                slot_of(slots, var);
+            }
+        },
+        _ => {}
+    }
+    items.iter().for_each(|item| frame_names(item, slots));
+}
+
+impl<'a> Resolver<'a> {
+
+    fn new(interp: &'a Interpreter, definitions: Definitions) -> Self {
+        Resolver {
+            interp,
+            frames: Vec::new(),
+            definitions,
+            unbound: None,
+        }
+    }
+
+    fn lookup(&self, name: Symbol) -> Option<Local> {
+        self.frames.iter().rev().enumerate().find_map(|(depth, frame)| {
+            frame.iter().position(|slot| *slot == name).map(
+                |slot| Local { depth: depth as u32, slot: slot as u32, name }
+            )
+        })
+    }
+
+    // Remembers the first global that is never bound
+    fn check(&mut self, name: Symbol) {
+        if !self.definitions.checked || self.unbound.is_some() || self.definitions.contains(name)
+            || self.interp.env.get(name).is_some() {
+            return;
+        }
+        let imported = self.definitions.modules.iter().any(|module| {
+            name.name().strip_prefix(module.name()).is_some_and(|rest| rest.starts_with('/'))
+        });
+        if !imported {
+            self.unbound = Some(name);
+        }
+    }
+
+    fn exp(&mut self, exp: &LispExp) -> LispExp {
+        match exp {
+            LispExp::Symbol(s) if s.is_keyword() => exp.clone(),
+            LispExp::Symbol(s) => match self.lookup(*s) {
+                Some(local) => LispExp::Local(local),
+                None => {
+                    self.check(*s);
+                    exp.clone()
+                }
+            },
+            LispExp::List(items) => match &items[..] {
+                [LispExp::Symbol(Symbol::LAMBDA), args @ ..] if args.len() >= 2 => {
+                    let (body, params) = args.split_last().unwrap();
+                    LispExp::Procedure(Rc::new(self.procedure(params, body)))
+                },
+                _ => LispExp::from(self.form(items)),
+            },
+            _ => exp.clone(),
+        }
+    }
+
+    fn exps(&mut self, exps: &[LispExp]) -> Vec<LispExp> {
+        exps.iter().map(|exp| self.exp(exp)).collect()
+    }
+
+    // The items of a list evaluated as a form. Malformed forms are left as
+    // they are, they fail when evaluated.
+    fn form(&mut self, items: &[LispExp]) -> Vec<LispExp> {
+        let (head, args) = match items.split_first() {
+            Some((LispExp::Symbol(head), args)) => (*head, args),
+            // A list in head position is evaluated before the rest of the list
+            Some((LispExp::List(first), tail)) => {
+                let mut form = vec![LispExp::from(self.form(first))];
+                form.extend(self.form(tail));
+                return form;
+            },
+            _ => return items.to_vec()
+        };
+        let mut form = vec![LispExp::Symbol(head)];
+        match (head, args) {
+            (Symbol::QUOTE | Symbol::MODULE | Symbol::IMPORT | Symbol::DEFMACRO | Symbol::LAMBDA, _) => {
+                form.extend_from_slice(args);
+            },
+            (Symbol::DEFINE, [name, value]) => {
+                form.push(self.target(name));
+                form.push(self.exp(value));
+            },
+            (Symbol::FN, [name, rest @ ..]) if rest.len() >= 2 => {
+                let (body, params) = rest.split_last().unwrap();
+                let procedure = self.procedure(params, body);
+                form = vec![
+                    LispExp::Symbol(Symbol::DEFINE),
+                    self.target(name),
+                    LispExp::Procedure(Rc::new(procedure))
+                ];
+            },
+            (Symbol::FN | Symbol::DEFINE, _) => form.extend_from_slice(args),
+            (Symbol::DO, [LispExp::List(specs), LispExp::List(clause), body @ ..]) => {
+                let specs: Vec<LispExp> = specs.iter().map(|spec| self.loop_header(spec)).collect();
+                form.push(LispExp::from(specs));
+                let clause = self.exps(clause);
+                form.push(LispExp::from(clause));
+                form.extend(self.exps(body));
+            },
+            (Symbol::DOTIMES | Symbol::DOLIST, [header, body @ ..]) => {
+                form.push(self.loop_header(header));
+                form.extend(self.exps(body));
+            },
+            (Symbol::DO | Symbol::DOTIMES | Symbol::DOLIST, _) => form.extend_from_slice(args),
+            (Symbol::LOAD | Symbol::IF | Symbol::BEGIN | Symbol::WHILE, _) => form.extend(self.exps(args)),
+            _ => {
+                let local = self.lookup(head);
+                if let Some(local) = local {
+                    form[0] = LispExp::Local(local);
+                }
+                if self.definitions.macros.contains(&head)
+                    || (local.is_none() && self.definitions.is_macro(head, self.interp)) {
+                    form.extend_from_slice(args);
+                } else {
+                    form.extend(self.exps(args));
+                }
+            }
+        }
+        form
+    }
+
+    // The variable a definition binds, a slot in procedures
+    fn target(&mut self, name: &LispExp) -> LispExp {
+        match (name, self.frames.last()) {
+            (LispExp::Symbol(s), Some(frame)) => match frame.iter().position(|slot| slot == s) {
+                Some(slot) => LispExp::Local(Local { depth: 0, slot: slot as u32, name: *s }),
+                None => name.clone()
+            },
+            _ => name.clone()
+        }
+    }
+
+    // (var expressions...) where the variable is bound by name
+    fn loop_header(&mut self, header: &LispExp) -> LispExp {
+        match header {
+            LispExp::List(items) if loop_var(header).is_some() => {
+                let mut resolved = vec![items[0].clone()];
+                resolved.extend(self.exps(&items[1..]));
+                LispExp::from(resolved)
+            },
+            _ => header.clone()
+        }
+    }
+
+    fn procedure(&mut self, params: &[LispExp], body: &LispExp) -> Procedure {
+        let mut slots = Vec::new();
+        let mut bindings = Vec::new();
+
+        let mut names = params.iter();
+        while let Some(param) = names.next() {
+            let binding = match param {
+                LispExp::Symbol(Symbol::REST) => {
+                    Param::Rest(names.next().map(|name| slot_of(&mut slots, binding_name(name))))
+                },
+                _ => Param::Slot(slot_of(&mut slots, binding_name(param))),
+            };
+            bindings.push(binding);
+        }
+        frame_names(body, &mut slots);
+
+        self.frames.push(slots);
+        let body = self.exp(body);
+        let slots = self.frames.pop().unwrap();
+
+        Procedure {
+            params: params.into(),
+            bindings,
+            slots: slots.into(),
+            body,
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use std::rc::Rc;
+
+use serde::ser::{self, Serialize};
+
+use crate::list::List;
+use crate::eval::LispEval;
+
+use crate::utils::exceptions::SerdeError;
+
+// Serializes Rust values into rlisp values. Sequences become lists, maps and
+// structs become maps.
+pub struct Serializer;
+
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<LispEval, SerdeError> {
+    value.serialize(Serializer)
+}
+
+// Serializes a Rust value into rlisp source which evaluates back to it
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, SerdeError> {
+    to_source(&to_value(value)?)
+}
+
+// Prints a value as an rlisp expression evaluating to the same value
+pub fn to_source(value: &LispEval) -> Result<String, SerdeError> {
+    match value {
+        LispEval::Void => Ok("(void)".to_string()),
+        LispEval::Bool(_) | LispEval::Number(_) | LispEval::Str(_) => Ok(value.to_string()),
+        LispEval::Symbol(s) if s.is_keyword() => Ok(s.to_string()),
+        LispEval::Symbol(s) => Ok(format!("'{}", s)),
+        LispEval::List(list) if list.is_empty() => Ok("'()".to_string()),
+        LispEval::List(list) => {
+            let items = list.iter().map(to_source).collect::<Result<Vec<String>, SerdeError>>()?;
+            Ok(format!("(list {})", items.join(" ")))
+        },
+        LispEval::Vector(vector) => {
+            let items = vector.borrow().iter().map(to_source).collect::<Result<Vec<String>, SerdeError>>()?;
+            Ok(format!("[{}]", items.join(" ")))
+        },
+        LispEval::Set(set) => {
+            let items = set.iter().map(to_source).collect::<Result<Vec<String>, SerdeError>>()?;
+            Ok(format!("{{{}}}", items.join(" ")))
+        },
+        LispEval::Map(map) => {
+            let mut items = Vec::new();
+            for (key, value) in map.iter() {
+                items.push(to_source(key)?);
+                items.push(to_source(value)?);
+            }
+            Ok(format!("#{{{}}}", items.join(" ")))
+        },
+        _ => Err(SerdeError::Message(format!("cannot serialize {}", value)))
+    }
+}
+
+fn map_value(entries: Vec<(LispEval, LispEval)>) -> LispEval {
+    LispEval::Map(Rc::new(entries.into_iter().collect()))
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = LispEval;
+    type Error = SerdeError;
+
+    type SerializeSeq = SerializeVec;
+    type SerializeTuple = SerializeVec;
+    type SerializeTupleStruct = SerializeVec;
+    type SerializeTupleVariant = SerializeVariant<SerializeVec>;
+    type SerializeMap = SerializeMap;
+    type SerializeStruct = SerializeMap;
+    type SerializeStructVariant = SerializeVariant<SerializeMap>;
+
+    fn serialize_bool(self, v: bool) -> Result<LispEval, SerdeError> {
+        Ok(LispEval::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<LispEval, SerdeError> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<LispEval, SerdeError> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<LispEval, SerdeError> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<LispEval, SerdeError> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<LispEval, SerdeError> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<LispEval, SerdeError> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<LispEval, SerdeError> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<LispEval, SerdeError> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<LispEval, SerdeError> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<LispEval, SerdeError> {
+        Ok(LispEval::Number(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<LispEval, SerdeError> {
+        Ok(LispEval::Str(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<LispEval, SerdeError> {
+        Ok(LispEval::Str(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<LispEval, SerdeError> {
+        Ok(LispEval::List(v.iter().map(|b| LispEval::Number(*b as f64)).collect()))
+    }
+
+    fn serialize_none(self) -> Result<LispEval, SerdeError> {
+        self.serialize_unit()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<LispEval, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<LispEval, SerdeError> {
+        Ok(LispEval::List(List::new()))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<LispEval, SerdeError> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str
+    ) -> Result<LispEval, SerdeError> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T
+    ) -> Result<LispEval, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T
+    ) -> Result<LispEval, SerdeError> {
+        Ok(map_value(vec![(LispEval::Str(variant.to_string()), to_value(value)?)]))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeVec, SerdeError> {
+        Ok(SerializeVec {
+            items: Vec::with_capacity(len.unwrap_or(0))
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeVec, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize
+    ) -> Result<SerializeVec, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize
+    ) -> Result<SerializeVariant<SerializeVec>, SerdeError> {
+        Ok(SerializeVariant {
+            variant,
+            inner: self.serialize_seq(Some(len))?
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<SerializeMap, SerdeError> {
+        Ok(SerializeMap {
+            entries: Vec::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize
+    ) -> Result<SerializeMap, SerdeError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize
+    ) -> Result<SerializeVariant<SerializeMap>, SerdeError> {
+        Ok(SerializeVariant {
+            variant,
+            inner: self.serialize_map(Some(len))?
+        })
+    }
+}
+
+pub struct SerializeVec {
+    items: Vec<LispEval>,
+}
+
+impl ser::SerializeSeq for SerializeVec {
+    type Ok = LispEval;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.items.push(to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<LispEval, SerdeError> {
+        Ok(LispEval::List(List::from(self.items)))
+    }
+}
+
+impl ser::SerializeTuple for SerializeVec {
+    type Ok = LispEval;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<LispEval, SerdeError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeVec {
+    type Ok = LispEval;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<LispEval, SerdeError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+pub struct SerializeMap {
+    entries: Vec<(LispEval, LispEval)>,
+    key: Option<LispEval>,
+}
+
+impl ser::SerializeMap for SerializeMap {
+    type Ok = LispEval;
+    type Error = SerdeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SerdeError> {
+        self.key = Some(to_value(key)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        let key = self.key.take().ok_or(
+            SerdeError::Message("key is missing".to_string())
+        )?;
+        self.entries.push((key, to_value(value)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<LispEval, SerdeError> {
+        Ok(map_value(self.entries))
+    }
+}
+
+impl ser::SerializeStruct for SerializeMap {
+    type Ok = LispEval;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T
+    ) -> Result<(), SerdeError> {
+        self.entries.push((LispEval::Str(key.to_string()), to_value(value)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<LispEval, SerdeError> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+// Wraps the contents of a tuple or struct variant in a map with a single "Variant" key
+pub struct SerializeVariant<S> {
+    variant: &'static str,
+    inner: S,
+}
+
+impl<S> SerializeVariant<S> {
+    fn wrap(variant: &str, value: LispEval) -> LispEval {
+        map_value(vec![(LispEval::Str(variant.to_string()), value)])
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeVariant<SerializeVec> {
+    type Ok = LispEval;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        ser::SerializeSeq::serialize_element(&mut self.inner, value)
+    }
+
+    fn end(self) -> Result<LispEval, SerdeError> {
+        Ok(Self::wrap(self.variant, ser::SerializeSeq::end(self.inner)?))
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeVariant<SerializeMap> {
+    type Ok = LispEval;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T
+    ) -> Result<(), SerdeError> {
+        ser::SerializeStruct::serialize_field(&mut self.inner, key, value)
+    }
+
+    fn end(self) -> Result<LispEval, SerdeError> {
+        Ok(Self::wrap(self.variant, ser::SerializeMap::end(self.inner)?))
+    }
+}
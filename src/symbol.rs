@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::sync::{Mutex, OnceLock};
+
+// An interned name. Symbols with the same name share the same id, so they
+// are compared and hashed as integers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+// Set in the ids of keywords, so telling them apart needs no name lookup
+const KEYWORD: u32 = 1 << 31;
+
+// Names of the special forms and of the symbols the parser inserts, interned
+// first so their symbols are constants
+const PREDEFINED: [(Symbol, &str); 20] = [
+    (Symbol::QUOTE, "quote"),
+    (Symbol::LOAD, "load"),
+    (Symbol::MODULE, "module"),
+    (Symbol::IMPORT, "import"),
+    (Symbol::EXPORT, "export"),
+    (Symbol::IF, "if"),
+    (Symbol::BEGIN, "begin"),
+    (Symbol::DEFINE, "define"),
+    (Symbol::LAMBDA, "lambda"),
+    (Symbol::FN, "fn"),
+    (Symbol::DEFMACRO, "defmacro"),
+    (Symbol::WHILE, "while"),
+    (Symbol::DO, "do"),
+    (Symbol::DOTIMES, "dotimes"),
+    (Symbol::DOLIST, "dolist"),
+    (Symbol::REST, "&rest"),
+    (Symbol::LIST, "list"),
+    (Symbol::SET, "set"),
+    (Symbol::HASH_MAP, "hash-map"),
+    (Symbol::VECTOR, "vector"),
+];
+
+struct Interner {
+    ids: HashMap<&'static str, u32>,
+    names: Vec<&'static str>,
+    gensyms: usize,
+}
+
+impl Interner {
+
+    fn new() -> Self {
+        let mut interner = Interner {
+            ids: HashMap::new(),
+            names: Vec::new(),
+            gensyms: 0,
+        };
+        for (symbol, name) in PREDEFINED {
+            let id = interner.intern(name);
+            debug_assert_eq!(id, symbol.0);
+        }
+        interner
+    }
+
+    fn intern(&mut self, name: &str) -> u32 {
+        if let Some(id) = self.ids.get(name) {
+            return *id;
+        }
+        // Interned names live as long as the program
+        let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+        let mut id = self.names.len() as u32;
+        if is_keyword_name(name) {
+            id |= KEYWORD;
+        }
+        self.names.push(name);
+        self.ids.insert(name, id);
+        id
+    }
+}
+
+fn interner() -> &'static Mutex<Interner> {
+    static INTERNER: OnceLock<Mutex<Interner>> = OnceLock::new();
+    INTERNER.get_or_init(|| Mutex::new(Interner::new()))
+}
+
+impl Symbol {
+
+    pub const QUOTE: Symbol = Symbol(0);
+    pub const LOAD: Symbol = Symbol(1);
+    pub const MODULE: Symbol = Symbol(2);
+    pub const IMPORT: Symbol = Symbol(3);
+    pub const EXPORT: Symbol = Symbol(4);
+    pub const IF: Symbol = Symbol(5);
+    pub const BEGIN: Symbol = Symbol(6);
+    pub const DEFINE: Symbol = Symbol(7);
+    pub const LAMBDA: Symbol = Symbol(8);
+    pub const FN: Symbol = Symbol(9);
+    pub const DEFMACRO: Symbol = Symbol(10);
+    pub const WHILE: Symbol = Symbol(11);
+    pub const DO: Symbol = Symbol(12);
+    pub const DOTIMES: Symbol = Symbol(13);
+    pub const DOLIST: Symbol = Symbol(14);
+    pub const REST: Symbol = Symbol(15);
+    pub const LIST: Symbol = Symbol(16);
+    pub const SET: Symbol = Symbol(17);
+    pub const HASH_MAP: Symbol = Symbol(18);
+    pub const VECTOR: Symbol = Symbol(19);
+
+    pub fn intern(name: &str) -> Self {
+        Symbol(interner().lock().unwrap().intern(name))
+    }
+
+    // The symbol with the name, if it was interned
+    pub fn find(name: &str) -> Option<Self> {
+        interner().lock().unwrap().ids.get(name).map(|id| Symbol(*id))
+    }
+
+    // A fresh symbol whose name was never interned before
+    pub fn gensym(prefix: &str) -> Self {
+        let mut interner = interner().lock().unwrap();
+        loop {
+            interner.gensyms += 1;
+            let name = format!("{}{}", prefix, interner.gensyms);
+            if !interner.ids.contains_key(name.as_str()) {
+                return Symbol(interner.intern(&name));
+            }
+        }
+    }
+
+    pub fn id(self) -> u32 {
+        self.0
+    }
+
+    pub fn name(self) -> &'static str {
+        interner().lock().unwrap().names[(self.0 & !KEYWORD) as usize]
+    }
+
+    // Keywords are symbols written with a leading colon, like :name
+    pub fn is_keyword(self) -> bool {
+        self.0 & KEYWORD != 0
+    }
+}
+
+pub fn is_keyword_name(name: &str) -> bool {
+    name.len() > 1 && name.starts_with(':')
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Symbol::intern(name)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// Hashes symbol ids with a single multiplication, spreading consecutive ids
+// over the high bits the hash table relies on
+#[derive(Default)]
+pub struct SymbolHasher(u64);
+
+impl Hasher for SymbolHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0.rotate_left(8) ^ u64::from(*byte)).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        }
+    }
+
+    fn write_u32(&mut self, id: u32) {
+        self.0 = (self.0 ^ u64::from(id)).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    }
+}
+
+// A map keyed on symbols, used for environments
+pub type SymbolMap<V> = HashMap<Symbol, V, BuildHasherDefault<SymbolHasher>>;
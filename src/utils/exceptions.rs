@@ -1,26 +1,172 @@
+use std::fmt;
+
+use crate::native::Arity;
+use crate::limits::Resource;
+
 #[derive(Debug, PartialEq)]
 pub enum ParseError {
   EmptyInput(),
   UnexpectedToken(String),
   UnexpectedExpression(),
   MissingToken,
+  UnterminatedString(),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum EvalError {
   DefaultError(),
   UnknownSymbol(String),
   UnexpectedExpression(),
   InvalidNumberOfArguments(),
+  ArityMismatch(String, Arity, usize),
   InvalidAgrumentType(),
+  EmptySequence(),
+  IndexOutOfRange(usize),
+  ModuleNotFound(String),
+  InvalidModule(String),
+  LoadError(String),
+  LoadCycle(String),
+  InvalidMacroExpansion(String),
+  PermissionDenied(String),
+  IoError(String),
+  ResourceExhausted(Resource),
+  Interrupted,
+  ConversionError(String),
   InvalidListExpression(),
   InvalidIfStatement(),
+  InvalidLoopStatement(),
   InvalidFunctionCall,
   NonDefineInThisScope,
 }
 
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ParseError::EmptyInput() => write!(f, "empty input"),
+      ParseError::UnexpectedToken(token) => write!(f, "unexpected token {}", token),
+      ParseError::UnexpectedExpression() => write!(f, "unexpected expression"),
+      ParseError::MissingToken => write!(f, "unexpected end of input"),
+      ParseError::UnterminatedString() => write!(f, "unterminated string"),
+    }
+  }
+}
+
+impl fmt::Display for EvalError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      EvalError::DefaultError() => write!(f, "evaluation failed"),
+      EvalError::UnknownSymbol(name) => write!(f, "unknown symbol {}", name),
+      EvalError::UnexpectedExpression() => write!(f, "unexpected expression"),
+      EvalError::InvalidNumberOfArguments() => write!(f, "wrong number of arguments"),
+      EvalError::ArityMismatch(name, arity, count) => {
+        write!(f, "wrong number of arguments to {}: expected {}, got {}", name, arity, count)
+      },
+      EvalError::InvalidAgrumentType() => write!(f, "invalid argument type"),
+      EvalError::EmptySequence() => write!(f, "empty sequence"),
+      EvalError::IndexOutOfRange(index) => write!(f, "index {} out of range", index),
+      EvalError::ModuleNotFound(name) => write!(f, "module {} not found", name),
+      EvalError::InvalidModule(name) => write!(f, "invalid module {}", name),
+      EvalError::LoadError(message) => write!(f, "cannot load {}", message),
+      EvalError::LoadCycle(path) => write!(f, "{} loads itself", path),
+      EvalError::InvalidMacroExpansion(exp) => write!(f, "invalid macro expansion {}", exp),
+      EvalError::PermissionDenied(path) => write!(f, "permission denied: {}", path),
+      EvalError::IoError(message) => write!(f, "{}", message),
+      EvalError::ResourceExhausted(Resource::Fuel) => write!(f, "out of fuel"),
+      EvalError::ResourceExhausted(Resource::Depth) => write!(f, "maximum call depth exceeded"),
+      EvalError::ResourceExhausted(Resource::Memory) => write!(f, "out of memory"),
+      EvalError::Interrupted => write!(f, "interrupted"),
+      EvalError::ConversionError(message) => write!(f, "{}", message),
+      EvalError::InvalidListExpression() => write!(f, "invalid list expression"),
+      EvalError::InvalidIfStatement() => write!(f, "invalid if expression"),
+      EvalError::InvalidLoopStatement() => write!(f, "invalid loop"),
+      EvalError::InvalidFunctionCall => write!(f, "invalid procedure call"),
+      EvalError::NonDefineInThisScope => write!(f, "procedure not defined in this scope"),
+    }
+  }
+}
+
 #[derive(Debug)]
 pub enum ReaderError {
   UnexpectedSymbol(char),
   MissingSymbol(char)
-}
\ No newline at end of file
+}
+
+// Any error produced while running rlisp source through the interpreter
+#[derive(Debug)]
+pub enum LispError {
+  Parse(ParseError),
+  Eval(EvalError),
+  Io(std::io::Error),
+}
+
+impl fmt::Display for LispError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      LispError::Parse(e) => write!(f, "syntax error: {}", e),
+      LispError::Eval(e) => write!(f, "{}", e),
+      LispError::Io(e) => write!(f, "{}", e),
+    }
+  }
+}
+
+impl std::error::Error for LispError {}
+
+impl From<ParseError> for LispError {
+  fn from(e: ParseError) -> Self {
+    LispError::Parse(e)
+  }
+}
+
+impl From<EvalError> for LispError {
+  fn from(e: EvalError) -> Self {
+    LispError::Eval(e)
+  }
+}
+
+impl From<std::io::Error> for LispError {
+  fn from(e: std::io::Error) -> Self {
+    LispError::Io(e)
+  }
+}
+
+// Errors of the serde Serializer and Deserializer
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub enum SerdeError {
+  Message(String),
+  Lisp(LispError),
+}
+
+#[cfg(feature = "serde")]
+impl fmt::Display for SerdeError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      SerdeError::Message(msg) => write!(f, "{}", msg),
+      SerdeError::Lisp(e) => write!(f, "{}", e),
+    }
+  }
+}
+
+#[cfg(feature = "serde")]
+impl std::error::Error for SerdeError {}
+
+#[cfg(feature = "serde")]
+impl serde::de::Error for SerdeError {
+  fn custom<T: std::fmt::Display>(msg: T) -> Self {
+    SerdeError::Message(msg.to_string())
+  }
+}
+
+#[cfg(feature = "serde")]
+impl serde::ser::Error for SerdeError {
+  fn custom<T: std::fmt::Display>(msg: T) -> Self {
+    SerdeError::Message(msg.to_string())
+  }
+}
+
+#[cfg(feature = "serde")]
+impl From<LispError> for SerdeError {
+  fn from(e: LispError) -> Self {
+    SerdeError::Lisp(e)
+  }
+}
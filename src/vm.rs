@@ -0,0 +1,423 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::exp::LispExp;
+use crate::eval::{LispEval, LispLambda, eval_exp, apply, sequence_items};
+use crate::interpreter::Interpreter;
+use crate::list::List;
+use crate::compiler::compile;
+use crate::symbol::Symbol;
+
+use crate::utils::exceptions::EvalError;
+
+// Instructions of the virtual machine. Every expression pushes exactly one
+// value on the stack, jump targets are indexes in the code of the function.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Op {
+    Const(u32),
+    Void,
+    // Variable in the frame `depth` levels up, unset slots fall back to the
+    // variables of the enclosing frames with the name and to the globals
+    Local { depth: u32, slot: u32, name: Symbol },
+    Global(Symbol),
+    // Like Local and Global, for a procedure about to be called
+    LocalCallee { depth: u32, slot: u32, name: Symbol },
+    GlobalCallee(Symbol),
+    SetLocal(u32),
+    Unset(u32),
+    DefineGlobal(Symbol),
+    UndefineGlobal(Symbol),
+    // Keeps the value of a global in a slot, restored or removed later
+    SaveGlobal { name: Symbol, slot: u32 },
+    RestoreGlobal { name: Symbol, slot: u32 },
+    // Copies a slot of the frame, set or not, to another one
+    CopyLocal { from: u32, to: u32 },
+    Pop,
+    Jump(u32),
+    // Pops the condition of an if, jumps when it is false
+    If(u32),
+    // Pops the condition of a loop, jumps when it has the given value
+    Condition(u32, bool),
+    // Jumps back to the start of a loop
+    Loop(u32),
+    Call(u32),
+    Closure(u32),
+    // Evaluates an expression with the tree-walking evaluator
+    Eval(u32),
+    Raise(u32),
+    Return,
+    // Loop helpers: dotimes counts in a slot up to a limit, dolist walks the
+    // list on top of the stack
+    CheckNumber,
+    Below { counter: u32, limit: u32, exit: u32 },
+    Increment(u32),
+    ToList,
+    Next(u32),
+}
+
+// Parameter of a compiled procedure
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Param {
+    Slot(u32),
+    // &rest followed by the slot of its name, if any
+    Rest(Option<u32>),
+}
+
+// A compiled procedure or top level expression
+#[derive(Default)]
+pub(crate) struct Function {
+    pub(crate) code: Vec<Op>,
+    pub(crate) constants: Vec<LispEval>,
+    pub(crate) functions: Vec<Rc<Function>>,
+    pub(crate) exps: Vec<LispExp>,
+    pub(crate) errors: Vec<EvalError>,
+    pub(crate) params: Vec<Param>,
+    // Names of the variables in the slots of a frame, None for the hidden
+    // slots of loops
+    pub(crate) slots: Vec<Option<Symbol>>,
+}
+
+// The variables of a procedure call, linked to the frame the procedure was
+// created in
+pub(crate) struct Frame {
+    function: Rc<Function>,
+    slots: RefCell<Vec<Option<LispEval>>>,
+    parent: Option<Rc<Frame>>,
+}
+
+impl Frame {
+
+    fn new(function: Rc<Function>, slots: Vec<Option<LispEval>>, parent: Option<Rc<Frame>>) -> Rc<Self> {
+        Rc::new(Frame {
+            function,
+            slots: RefCell::new(slots),
+            parent,
+        })
+    }
+
+    // The set variable with the name, if any
+    fn find(&self, name: Symbol) -> Option<LispEval> {
+        let slots = self.slots.borrow();
+        self.function.slots.iter().zip(slots.iter())
+            .find(|(slot, value)| **slot == Some(name) && value.is_some())
+            .and_then(|(_, value)| value.clone())
+    }
+
+    fn get(&self, slot: u32) -> Option<LispEval> {
+        self.slots.borrow()[slot as usize].clone()
+    }
+
+    fn set(&self, slot: u32, value: LispEval) {
+        self.slots.borrow_mut()[slot as usize] = Some(value);
+    }
+
+    fn number(&self, slot: u32) -> f64 {
+        match self.slots.borrow()[slot as usize] {
+            Some(LispEval::Number(n)) => n,
+            _ => unreachable!("loop counters hold numbers")
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Closure {
+    function: Rc<Function>,
+    frame: Option<Rc<Frame>>,
+}
+
+// The state of a caller while the procedure it called runs
+struct Activation {
+    function: Rc<Function>,
+    frame: Rc<Frame>,
+    ip: usize,
+    globals: usize,
+}
+
+#[derive(Default)]
+struct Machine {
+    stack: Vec<LispEval>,
+    calls: Vec<Activation>,
+}
+
+// Compiles and runs an expression in the global environment
+pub(crate) fn eval(exp: &LispExp, interp: &mut Interpreter) -> Result<LispEval, EvalError> {
+    let scope = interp.scope.take();
+    let function = compile(exp, interp);
+    interp.scope = scope;
+
+    let frame = Frame::new(function.clone(), vec![None; function.slots.len()], None);
+    let globals = interp.modules.current;
+    run(function, frame, globals, interp)
+}
+
+// Calls a compiled procedure from the tree-walker or a builtin
+pub(crate) fn call(closure: &Closure, globals: usize, args: &[LispEval], interp: &mut Interpreter
+) -> Result<LispEval, EvalError> {
+    interp.nested(|interp| {
+        let frame = bind(closure, args.iter().cloned())?;
+        run(closure.function.clone(), frame, globals, interp)
+    })
+}
+
+fn run(function: Rc<Function>, frame: Rc<Frame>, globals: usize, interp: &mut Interpreter
+) -> Result<LispEval, EvalError> {
+    let scope = interp.scope.take();
+    let previous = interp.switch_globals(globals);
+
+    let mut machine = Machine::default();
+    let result = machine.execute(function, frame, interp);
+
+    // Calls interrupted by an error are left
+    for _ in &machine.calls {
+        interp.exit_call();
+    }
+    interp.switch_globals(previous);
+    interp.scope = scope;
+    result
+}
+
+// Binds the arguments of a call in a new frame, the same way as the
+// tree-walker does
+fn bind<I>(closure: &Closure, args: I) -> Result<Rc<Frame>, EvalError>
+where
+    I: ExactSizeIterator<Item = LispEval>
+{
+    let function = &closure.function;
+    let mut slots = vec![None; function.slots.len()];
+    let mut args = args;
+    for param in &function.params {
+        match *param {
+            Param::Rest(Some(slot)) => {
+                slots[slot as usize] = Some(LispEval::List(args.by_ref().collect()));
+            },
+            Param::Rest(None) => return Err(EvalError::InvalidNumberOfArguments()),
+            Param::Slot(slot) => match args.next() {
+                Some(arg) => slots[slot as usize] = Some(arg),
+                None => return Err(EvalError::InvalidNumberOfArguments()),
+            }
+        }
+    }
+    if args.next().is_some() {
+        return Err(EvalError::InvalidNumberOfArguments());
+    }
+    Ok(Frame::new(function.clone(), slots, closure.frame.clone()))
+}
+
+fn frame_at(frame: &Rc<Frame>, depth: u32) -> &Rc<Frame> {
+    let mut frame = frame;
+    for _ in 0..depth {
+        frame = frame.parent.as_ref().expect("frames are resolved at compile time");
+    }
+    frame
+}
+
+fn global(name: Symbol, interp: &Interpreter) -> Option<LispEval> {
+    interp.env.get(name).cloned()
+}
+
+// Looks up a variable whose slot is not set in the frames enclosing its own,
+// then in the globals, like the tree-walker does
+fn outer(frame: &Rc<Frame>, name: Symbol, interp: &Interpreter) -> Option<LispEval> {
+    let mut next = frame.parent.as_ref();
+    while let Some(frame) = next {
+        if let Some(value) = frame.find(name) {
+            return Some(value);
+        }
+        next = frame.parent.as_ref();
+    }
+    global(name, interp)
+}
+
+impl Machine {
+
+    fn pop(&mut self) -> LispEval {
+        self.stack.pop().expect("expressions push a value")
+    }
+
+    fn execute(&mut self, function: Rc<Function>, frame: Rc<Frame>, interp: &mut Interpreter
+    ) -> Result<LispEval, EvalError> {
+
+        let mut function = function;
+        let mut frame = frame;
+        let mut ip = 0;
+
+        loop {
+            let op = function.code[ip];
+            ip += 1;
+
+            match op {
+                Op::Const(index) => self.stack.push(function.constants[index as usize].clone()),
+                Op::Void => self.stack.push(LispEval::Void),
+                Op::Local { depth, slot, name } => {
+                    let scope = frame_at(&frame, depth);
+                    let value = match scope.get(slot) {
+                        Some(value) => value,
+                        None => outer(scope, name, interp).ok_or_else(
+                            || EvalError::UnknownSymbol(name.to_string())
+                        )?
+                    };
+                    self.stack.push(value);
+                },
+                Op::Global(name) => {
+                    let value = global(name, interp).ok_or_else(
+                        || EvalError::UnknownSymbol(name.to_string())
+                    )?;
+                    self.stack.push(value);
+                },
+                Op::LocalCallee { depth, slot, name } => {
+                    let scope = frame_at(&frame, depth);
+                    let value = match scope.get(slot) {
+                        Some(value) => value,
+                        None => outer(scope, name, interp).ok_or(
+                            EvalError::NonDefineInThisScope
+                        )?
+                    };
+                    self.stack.push(value);
+                },
+                Op::GlobalCallee(name) => {
+                    let value = global(name, interp).ok_or(
+                        EvalError::NonDefineInThisScope
+                    )?;
+                    self.stack.push(value);
+                },
+                Op::SetLocal(slot) => {
+                    let value = self.pop();
+                    frame.set(slot, value);
+                },
+                Op::Unset(slot) => frame.slots.borrow_mut()[slot as usize] = None,
+                Op::UndefineGlobal(name) => interp.env.remove(name),
+                Op::SaveGlobal { name, slot } => {
+                    if let Some(value) = global(name, interp) {
+                        frame.set(slot, value);
+                    }
+                },
+                Op::RestoreGlobal { name, slot } => {
+                    match frame.get(slot) {
+                        Some(value) => interp.env.add(name, value),
+                        None => interp.env.remove(name),
+                    }
+                },
+                Op::CopyLocal { from, to } => {
+                    let value = frame.get(from);
+                    frame.slots.borrow_mut()[to as usize] = value;
+                },
+                Op::DefineGlobal(name) => {
+                    let value = self.pop();
+                    interp.env.add(name, value);
+                },
+                Op::Pop => {
+                    self.pop();
+                },
+                Op::Jump(target) => ip = target as usize,
+                Op::If(target) => match self.pop() {
+                    LispEval::Bool(true) => {},
+                    LispEval::Bool(false) => ip = target as usize,
+                    _ => return Err(EvalError::InvalidIfStatement())
+                },
+                Op::Condition(target, jump) => match self.pop() {
+                    LispEval::Bool(b) => if b == jump {
+                        ip = target as usize
+                    },
+                    _ => return Err(EvalError::InvalidLoopStatement())
+                },
+                Op::Loop(target) => {
+                    interp.step()?;
+                    ip = target as usize;
+                },
+                Op::Call(count) => {
+                    interp.step()?;
+                    let base = self.stack.len() - count as usize - 1;
+                    let compiled = match &self.stack[base] {
+                        LispEval::Lambda(lambda) => lambda.closure().map(
+                            |(closure, globals)| (closure.clone(), globals)
+                        ),
+                        _ => None
+                    };
+                    match compiled {
+                        Some((closure, globals)) => {
+                            interp.enter_call()?;
+                            let callee_frame = match bind(&closure, self.stack.drain(base + 1..)) {
+                                Ok(callee_frame) => callee_frame,
+                                Err(e) => {
+                                    interp.exit_call();
+                                    return Err(e);
+                                }
+                            };
+                            self.stack.truncate(base);
+                            let globals = interp.switch_globals(globals);
+                            self.calls.push(Activation {
+                                function: std::mem::replace(&mut function, closure.function),
+                                frame: std::mem::replace(&mut frame, callee_frame),
+                                ip,
+                                globals,
+                            });
+                            ip = 0;
+                        },
+                        None => {
+                            let value = apply(&self.stack[base], &self.stack[base + 1..], interp)?;
+                            self.stack.truncate(base);
+                            self.stack.push(value);
+                        }
+                    }
+                },
+                Op::Closure(index) => {
+                    let closure = Closure {
+                        function: function.functions[index as usize].clone(),
+                        frame: Some(frame.clone()),
+                    };
+                    let lambda = LispLambda::compiled(closure, interp.modules.current);
+                    self.stack.push(LispEval::Lambda(Rc::new(lambda)));
+                },
+                Op::Eval(index) => {
+                    let value = eval_exp(&function.exps[index as usize], interp)?;
+                    self.stack.push(value);
+                },
+                Op::Raise(index) => return Err(function.errors[index as usize].clone()),
+                Op::Return => match self.calls.pop() {
+                    Some(caller) => {
+                        interp.exit_call();
+                        interp.switch_globals(caller.globals);
+                        function = caller.function;
+                        frame = caller.frame;
+                        ip = caller.ip;
+                    },
+                    None => return Ok(self.pop()),
+                },
+                Op::CheckNumber => {
+                    if !matches!(self.stack.last(), Some(LispEval::Number(_))) {
+                        return Err(EvalError::InvalidAgrumentType());
+                    }
+                },
+                Op::Below { counter, limit, exit } => {
+                    if frame.number(counter) >= frame.number(limit) {
+                        ip = exit as usize;
+                    }
+                },
+                Op::Increment(slot) => {
+                    let n = frame.number(slot);
+                    frame.set(slot, LispEval::Number(n + 1.0));
+                },
+                Op::ToList => {
+                    let items = sequence_items(self.pop())?;
+                    self.stack.push(LispEval::List(List::from(items)));
+                },
+                Op::Next(exit) => {
+                    let (item, rest) = match self.stack.last() {
+                        Some(LispEval::List(list)) => (list.car().cloned(), list.cdr().cloned()),
+                        _ => unreachable!("dolist iterates over a list")
+                    };
+                    match (item, rest) {
+                        (Some(item), Some(rest)) => {
+                            *self.stack.last_mut().unwrap() = rest;
+                            self.stack.push(item);
+                        },
+                        _ => {
+                            self.pop();
+                            ip = exit as usize;
+                        }
+                    }
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,99 @@
+#![allow(unused)]
+
+use rlisp::impl_lisp_record;
+use rlisp::eval::LispEval;
+use rlisp::convert::*;
+use rlisp::interpreter::Interpreter;
+use rlisp::utils::exceptions::EvalError;
+
+use std::collections::{HashMap, HashSet};
+
+fn round_trip<T: IntoLisp + FromLisp>(value: T) -> T {
+    T::from_lisp(value.into_lisp()).unwrap()
+}
+
+#[test]
+fn test_primitive_conversions() {
+
+    assert!(round_trip(true));
+    assert_eq!(round_trip(4.5f64), 4.5);
+    assert_eq!(round_trip(-7i32), -7);
+    assert_eq!(round_trip(42usize), 42);
+    assert_eq!(round_trip("hello".to_string()), "hello");
+
+    assert!(i32::from_lisp(LispEval::Number(1.5)).is_err());
+    assert!(u8::from_lisp(LispEval::Number(-1.0)).is_err());
+    assert!(matches!(bool::from_lisp(LispEval::Number(1.0)), Err(EvalError::ConversionError(_))));
+}
+
+#[test]
+fn test_collection_conversions() {
+
+    assert_eq!(round_trip(vec![1.0, 2.0, 3.0]), vec![1.0, 2.0, 3.0]);
+    assert_eq!(round_trip(HashSet::from([1, 2, 3])), HashSet::from([1, 2, 3]));
+    assert_eq!(
+        round_trip(HashMap::from([("a".to_string(), 1), ("b".to_string(), 2)])),
+        HashMap::from([("a".to_string(), 1), ("b".to_string(), 2)])
+    );
+    assert_eq!(round_trip(Some(3)), Some(3));
+    assert_eq!(round_trip(None::<i32>), None);
+    assert_eq!(round_trip((1, "x".to_string(), false)), (1, "x".to_string(), false));
+
+    assert_eq!(vec![1, 2].into_lisp().to_string(), "(1 2)");
+    assert!(<(i32, i32)>::from_lisp(vec![1].into_lisp()).is_err());
+}
+
+#[derive(Debug, PartialEq)]
+struct Server {
+    host: String,
+    port: u16,
+    tags: Vec<String>,
+    backup: Option<String>,
+}
+
+impl_lisp_record!(Server { host, port, tags, backup });
+
+#[test]
+fn test_record_conversion() {
+
+    let server = Server {
+        host: "localhost".to_string(),
+        port: 8080,
+        tags: vec!["web".to_string()],
+        backup: None,
+    };
+    let server = round_trip(server);
+    assert_eq!(server.port, 8080);
+
+    let mut interp = Interpreter::new();
+    let value = interp.eval_str(
+        "(list (list \"host\" \"example.com\") (list \"port\" 80) (list \"tags\" '()))"
+    ).unwrap();
+    let server = Server::from_lisp(value).unwrap();
+
+    assert_eq!(server, Server {
+        host: "example.com".to_string(),
+        port: 80,
+        tags: vec![],
+        backup: None,
+    });
+}
+
+#[test]
+fn test_typed_call() {
+
+    let mut interp = Interpreter::new();
+    interp.eval_str("(fn plus a b (+ a b))").unwrap();
+
+    let sum = interp.call::<(f64, f64), f64>("plus", (3.0, 4.0)).unwrap();
+    assert_eq!(sum, 7.0);
+
+    let doubled: Vec<i64> = interp.call("map", (
+        interp.get_global("+").unwrap(),
+        vec![1, 2, 3],
+        vec![1, 2, 3],
+    )).unwrap();
+    assert_eq!(doubled, vec![2, 4, 6]);
+
+    assert!(interp.call::<(f64, f64), String>("plus", (3.0, 4.0)).is_err());
+}
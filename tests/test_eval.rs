@@ -3,6 +3,7 @@
 use rlisp::exp::*;
 use rlisp::env::*;
 use rlisp::eval::*;
+use rlisp::utils::exceptions::EvalError;
 
 #[test]
 fn test_eval_if_statement() {
@@ -89,4 +90,565 @@ fn test_define_lambda_as_variable() {
     } else {
         assert!(false);
     }
-}
\ No newline at end of file
+}
+
+fn eval_program(program: &[&str], env: &mut LispEnv) -> LispEval {
+    let mut value = LispEval::List(rlisp::list::List::new());
+    for input in program {
+        let tokens = tokenize(input.to_string());
+        let (exp, _) = parse(&tokens[..]).unwrap();
+        value = eval(exp, env).unwrap();
+    }
+    value
+}
+
+#[test]
+fn test_while_loop() {
+
+    let mut env = default_env();
+
+    eval_program(&[
+        "(define i 0)",
+        "(define sum 0)",
+        "(while (< i 5) (define sum (+ sum i)) (define i (+ i 1)))",
+    ], &mut env);
+
+    if let LispEval::Number(n) = env.get("sum").unwrap() {
+        assert_eq!(n, &10.0);
+    } else {
+        panic!();
+    }
+}
+
+#[test]
+fn test_do_loop() {
+
+    let mut env = default_env();
+
+    let value = eval_program(&[
+        "(do ((i 0 (+ i 1)) (acc 1 (* acc 2))) ((= i 10) acc))",
+    ], &mut env);
+
+    if let LispEval::Number(n) = value {
+        assert_eq!(n, 1024.0);
+    } else {
+        panic!();
+    }
+    assert!(env.get("i").is_none());
+    assert!(env.get("acc").is_none());
+}
+
+#[test]
+fn test_dotimes_loop() {
+
+    let mut env = default_env();
+
+    eval_program(&[
+        "(define i 42)",
+        "(define sum 0)",
+        "(dotimes (i 100000) (define sum (+ sum i)))",
+    ], &mut env);
+
+    if let LispEval::Number(n) = env.get("sum").unwrap() {
+        assert_eq!(n, &4999950000.0);
+    } else {
+        panic!();
+    }
+    if let LispEval::Number(n) = env.get("i").unwrap() {
+        assert_eq!(n, &42.0);
+    } else {
+        panic!();
+    }
+}
+
+#[test]
+fn test_dolist_loop() {
+
+    let mut env = default_env();
+
+    let value = eval_program(&[
+        "(define sum 0)",
+        "(dolist (x '(1 2 3) sum) (define sum (+ sum x)))",
+    ], &mut env);
+
+    if let LispEval::Number(n) = value {
+        assert_eq!(n, 6.0);
+    } else {
+        panic!();
+    }
+
+    let value = eval_program(&[
+        "(define sum 0)",
+        "(dolist (x {1 2 3 4} sum) (define sum (+ sum x)))",
+    ], &mut env);
+
+    if let LispEval::Number(n) = value {
+        assert_eq!(n, 10.0);
+    } else {
+        panic!();
+    }
+}
+
+#[test]
+fn test_for_each() {
+
+    let mut env = default_env();
+
+    let value = eval_program(&[
+        "(define seen [])",
+        "(for-each (lambda a b (vector-push! seen (+ a b))) '(1 2 3) '(4 5))",
+    ], &mut env);
+
+    assert!(matches!(value, LispEval::Void));
+    assert_eq!(eval_program(&["seen"], &mut env).to_string(), "[5 7]");
+
+    let tokens = tokenize("(for-each (lambda x (undefined x)) '(1 2))".to_string());
+    let (exp, _) = parse(&tokens[..]).unwrap();
+    assert!(eval(exp, &mut env).is_err());
+}
+
+#[test]
+fn test_map_and_filter() {
+
+    let mut env = default_env();
+
+    let value = eval_program(&["(map (lambda x (* x 2)) '(1 2 3))"], &mut env);
+    assert_eq!(value.to_string(), "(2 4 6)");
+
+    let value = eval_program(&["(map + '(1 2 3) '(10 20))"], &mut env);
+    assert_eq!(value.to_string(), "(11 22)");
+
+    let value = eval_program(&["(filter (lambda x (> x 1)) '(1 2 3))"], &mut env);
+    assert_eq!(value.to_string(), "(2 3)");
+
+    let value = eval_program(&["(filter (lambda x (> x 2)) {1 2 3})"], &mut env);
+    assert_eq!(value.to_string(), "{3}");
+}
+
+#[test]
+fn test_reduce_and_fold() {
+
+    let mut env = default_env();
+
+    let value = eval_program(&["(reduce + '(1 2 3 4))"], &mut env);
+    assert_eq!(value.to_string(), "10");
+
+    let value = eval_program(&["(fold-left - 0 '(1 2 3))"], &mut env);
+    assert_eq!(value.to_string(), "-6");
+
+    let value = eval_program(&["(fold-right cons '() '(1 2 3))"], &mut env);
+    assert_eq!(value.to_string(), "(1 2 3)");
+
+    let tokens = tokenize("(reduce + '())".to_string());
+    let (exp, _) = parse(&tokens[..]).unwrap();
+    assert!(eval(exp, &mut env).is_err());
+}
+
+#[test]
+fn test_search_functions() {
+
+    let mut env = default_env();
+
+    eval_program(&["(fn even n (= (% n 2) 0))"], &mut env);
+
+    let value = eval_program(&["(any even '(1 3 4))"], &mut env);
+    assert_eq!(value.to_string(), "true");
+
+    let value = eval_program(&["(every even {2 4 5})"], &mut env);
+    assert_eq!(value.to_string(), "false");
+
+    let value = eval_program(&["(find even '(1 6 4))"], &mut env);
+    assert_eq!(value.to_string(), "6");
+
+    let value = eval_program(&["(count even '(1 2 4 7 8))"], &mut env);
+    assert_eq!(value.to_string(), "3");
+}
+
+#[test]
+fn test_map_operations() {
+
+    let mut env = default_env();
+
+    eval_program(&["(define m #{1 \"one\" '(1 2) \"pair\"})"], &mut env);
+
+    let value = eval_program(&["(get m '(1 2))"], &mut env);
+    assert_eq!(value.to_string(), "\"pair\"");
+
+    let value = eval_program(&["(get m 3 \"none\")"], &mut env);
+    assert_eq!(value.to_string(), "\"none\"");
+
+    let value = eval_program(&["(contains-key? (dissoc m 1) 1)"], &mut env);
+    assert_eq!(value.to_string(), "false");
+
+    let value = eval_program(&["(get (assoc m 3 \"three\") 3)"], &mut env);
+    assert_eq!(value.to_string(), "\"three\"");
+
+    // Updates copy a shared map and leave the original unchanged
+    let value = eval_program(&[
+        "(define n (assoc m 1 \"uno\"))",
+        "(list (get m 1) (get n 1))"
+    ], &mut env);
+    assert_eq!(value.to_string(), "(\"one\" \"uno\")");
+
+    let value = eval_program(&["(get (merge m #{1 \"uno\"}) 1)"], &mut env);
+    assert_eq!(value.to_string(), "\"uno\"");
+
+    let value = eval_program(&["(reduce + (values #{\"a\" 1 \"b\" 2}))"], &mut env);
+    assert_eq!(value.to_string(), "3");
+
+    let value = eval_program(&["(map->list #{\"a\" 1})"], &mut env);
+    assert_eq!(value.to_string(), "((\"a\" 1))");
+
+    let value = eval_program(&["(contains-key? #{{1 2} 0} {2 1})"], &mut env);
+    assert_eq!(value.to_string(), "true");
+
+    let tokens = tokenize("(hash-map 1)".to_string());
+    let (exp, _) = parse(&tokens[..]).unwrap();
+    assert!(eval(exp, &mut env).is_err());
+
+    // A map first is updated, even with a missing value
+    let tokens = tokenize("(assoc m 1)".to_string());
+    let (exp, _) = parse(&tokens[..]).unwrap();
+    assert!(matches!(eval(exp, &mut env), Err(EvalError::InvalidNumberOfArguments())));
+}
+
+#[test]
+fn test_cons_cells() {
+
+    let mut env = default_env();
+
+    let value = eval_program(&["(cons 1 (cons 2 '()))"], &mut env);
+    assert_eq!(value.to_string(), "(1 2)");
+
+    let value = eval_program(&["(cons 1 2)"], &mut env);
+    assert_eq!(value.to_string(), "(1 . 2)");
+
+    let value = eval_program(&["(cons 1 (cons 2 3))"], &mut env);
+    assert_eq!(value.to_string(), "(1 2 . 3)");
+
+    let value = eval_program(&["(cdr (cons 1 2))"], &mut env);
+    assert_eq!(value.to_string(), "2");
+
+    let value = eval_program(&["(tail '(1 2 3))"], &mut env);
+    assert_eq!(value.to_string(), "(2 3)");
+
+    let value = eval_program(&["(car (tail '(1 2 3)))"], &mut env);
+    assert_eq!(value.to_string(), "2");
+
+    let tokens = tokenize("(cdr '())".to_string());
+    let (exp, _) = parse(&tokens[..]).unwrap();
+    assert!(eval(exp, &mut env).is_err());
+
+    let tokens = tokenize("(map + (cons 1 2))".to_string());
+    let (exp, _) = parse(&tokens[..]).unwrap();
+    assert!(eval(exp, &mut env).is_err());
+}
+
+#[test]
+fn test_long_list() {
+
+    let mut env = default_env();
+
+    let value = eval_program(&[
+        "(define xs '())",
+        "(dotimes (i 200000) (define xs (cons i xs)))",
+        "(define ys (cons -1 xs))",
+        "(= (head (tail ys)) (head xs))",
+    ], &mut env);
+    assert_eq!(value.to_string(), "true");
+
+    let value = eval_program(&["(reduce + xs)"], &mut env);
+    assert_eq!(value.to_string(), "19999900000");
+
+    // Dropping the environment must not recurse through the whole list
+    drop(env);
+}
+
+#[test]
+fn test_list_library() {
+
+    let mut env = default_env();
+
+    let cases = [
+        ("(cadr '(1 2 3))", "2"),
+        ("(cddr '(1 2 3))", "(3)"),
+        ("(caadr (list 1 '(2 3)))", "2"),
+        ("(length '(1 2 3))", "3"),
+        ("(length \"abc\")", "3"),
+        ("(append '(1 2) '() '(3))", "(1 2 3)"),
+        ("(append '(1) 2)", "(1 . 2)"),
+        ("(append)", "()"),
+        ("(reverse '(1 2 3))", "(3 2 1)"),
+        ("(nth '(1 2 3) 1)", "2"),
+        ("(last '(1 2 3))", "3"),
+        ("(take '(1 2 3) 2)", "(1 2)"),
+        ("(drop '(1 2 3) 2)", "(3)"),
+        ("(range 3)", "(0 1 2)"),
+        ("(range 1 3)", "(1 2)"),
+        ("(range 5 0 -2)", "(5 3 1)"),
+        ("(range 0 1 0.25)", "(0 0.25 0.5 0.75)"),
+        ("(range 3 1)", "()"),
+        ("(zip '(1 2 3) '(4 5))", "((1 4) (2 5))"),
+        ("(flatten (list 1 (list 2 '(3 4)) '() 5))", "(1 2 3 4 5)"),
+        ("(member 2 '(1 2 3))", "(2 3)"),
+        ("(member 4 '(1 2 3))", "()"),
+        ("(assoc 2 (list '(1 \"a\") '(2 \"b\")))", "(2 \"b\")"),
+        ("(assoc 3 (list '(1 \"a\")))", "()"),
+        ("(list-index (lambda x (> x 1)) '(1 2 3))", "1"),
+        ("(list-index (lambda x (> x 5)) '(1 2 3))", "()"),
+    ];
+    for (source, expected) in cases {
+        let value = eval_program(&[source], &mut env);
+        assert_eq!(value.to_string(), expected, "{}", source);
+    }
+
+    let errors = [
+        "(nth '(1 2) 2)",
+        "(nth '(1 2) -1)",
+        "(nth '(1 2) 0.5)",
+        "(nth 1 0)",
+        "(last '())",
+        "(take '(1) 2)",
+        "(drop '(1) 2)",
+        "(range 0 1 0)",
+        "(range 1e16 (+ 1e16 4))",
+        "(range 0 1 1e-300)",
+        "(range 0 (/ 1 0))",
+        "(length (cons 1 2))",
+        "(cadr '(1))",
+        "(reverse 1)",
+        "(assoc 1 '(2))",
+    ];
+    for source in errors {
+        let tokens = tokenize(source.to_string());
+        let (exp, _) = parse(&tokens[..]).unwrap();
+        assert!(eval(exp, &mut env).is_err(), "{}", source);
+    }
+
+    let tokens = tokenize("(nth '(1 2) 5)".to_string());
+    let (exp, _) = parse(&tokens[..]).unwrap();
+    assert!(matches!(eval(exp, &mut env), Err(EvalError::IndexOutOfRange(5))));
+}
+
+#[test]
+fn test_vectors() {
+
+    let mut env = default_env();
+
+    let value = eval_program(&[
+        "(define v [1 (+ 1 1) 3])",
+        "(define w v)",
+        "(vector-set! w 0 10)",
+        "(vector-push! v 4 5)",
+    ], &mut env);
+    assert_eq!(value.to_string(), "[10 2 3 4 5]");
+
+    let cases = [
+        ("(vector-ref v 0)", "10"),
+        ("(vector-length w)", "5"),
+        ("(length v)", "5"),
+        ("(vector-slice v 1 3)", "[2 3]"),
+        ("(vector-slice v 3)", "[4 5]"),
+        ("(vector->list v)", "(10 2 3 4 5)"),
+        ("(list->vector '(1 2))", "[1 2]"),
+        ("(map (lambda x (* x 2)) [1 2])", "[2 4]"),
+        ("(filter (lambda x (> x 1)) [1 2 3])", "[2 3]"),
+        ("(reduce + v)", "24"),
+        ("(contains-key? #{[1 2] 1} (vector 1 2))", "true"),
+        ("(get #{[1] \"one\"} [1])", "\"one\""),
+        ("(vector-push! (vector) v)", "[[10 2 3 4 5]]"),
+    ];
+    for (source, expected) in cases {
+        let value = eval_program(&[source], &mut env);
+        assert_eq!(value.to_string(), expected, "{}", source);
+    }
+
+    let errors = [
+        "(vector-ref v 5)",
+        "(vector-ref v -1)",
+        "(vector-set! v 5 0)",
+        "(vector-slice v 3 2)",
+        "(vector-slice v 0 6)",
+        "(vector-ref '(1) 0)",
+        // Vectors holding themselves are rejected
+        "(vector-push! v v)",
+        "(vector-set! v 0 (list 1 (cons 2 v)))",
+        "(vector-push! v #{1 (vector [v])})",
+    ];
+    for source in errors {
+        let tokens = tokenize(source.to_string());
+        let (exp, _) = parse(&tokens[..]).unwrap();
+        assert!(eval(exp, &mut env).is_err(), "{}", source);
+    }
+}
+
+#[test]
+fn test_symbols_and_keywords() {
+
+    let mut env = default_env();
+
+    let cases = [
+        ("'apple", "apple"),
+        (":name", ":name"),
+        ("(quote (a (b 1) \"c\"))", "(a (b 1) \"c\")"),
+        ("'(a (b 1) \"c\")", "(a (b 1) \"c\")"),
+        ("(symbol? (car '(a b)))", "true"),
+        ("'(1 (+ 1 1))", "(1 (+ 1 1))"),
+        ("(eq? 'apple (string->symbol \"apple\"))", "true"),
+        ("(eq? 'apple 'pear)", "false"),
+        ("(eq? :a :a (car (list :a)))", "true"),
+        ("(symbol? 'a)", "true"),
+        ("(symbol? :a)", "false"),
+        ("(symbol? \"a\")", "false"),
+        ("(keyword? :a)", "true"),
+        ("(keyword? 'a)", "false"),
+        ("(symbol->string 'apple)", "\"apple\""),
+        ("(symbol->string :name)", "\":name\""),
+        ("(get #{:x 1 :y 2} :y)", "2"),
+        ("(eq? (gensym) (gensym))", "false"),
+        ("(symbol? (gensym \"tmp\"))", "true"),
+    ];
+    for (source, expected) in cases {
+        let value = eval_program(&[source], &mut env);
+        assert_eq!(value.to_string(), expected, "{}", source);
+    }
+
+    let errors = [
+        "(symbol->string \"a\")",
+        "(string->symbol 'a)",
+        "(string->symbol \"\")",
+        "(gensym 1)",
+        "(quote a b)",
+    ];
+    for source in errors {
+        let tokens = tokenize(source.to_string());
+        let (exp, _) = parse(&tokens[..]).unwrap();
+        assert!(eval(exp, &mut env).is_err(), "{}", source);
+    }
+}
+
+#[test]
+fn test_void_results() {
+
+    let mut env = default_env();
+
+    let cases = [
+        ("(define x 1)", ""),
+        ("(fn f a a)", ""),
+        ("(while false 1)", ""),
+        ("(dotimes (i 2) i)", ""),
+        ("(dolist (i '(1 2)) i)", ""),
+        ("(for-each f '(1))", ""),
+        ("(void? (define y 2))", "true"),
+        ("(void? (void))", "true"),
+        ("(void? '())", "false"),
+        ("(null? '())", "true"),
+        ("(null? (void))", "false"),
+        ("(null? '(1))", "false"),
+        ("(list (void) 1)", "( 1)"),
+    ];
+    for (source, expected) in cases {
+        let value = eval_program(&[source], &mut env);
+        assert_eq!(value.to_string(), expected, "{}", source);
+    }
+
+    let value = eval_program(&["(define z 5)"], &mut env);
+    assert!(matches!(value, LispEval::Void));
+    assert_eq!(eval_program(&["z"], &mut env).to_string(), "5");
+}
+
+#[test]
+fn test_type_predicates() {
+
+    let mut env = default_env();
+
+    let cases = [
+        ("(type-of 1)", "number"),
+        ("(type-of \"s\")", "string"),
+        ("(type-of true)", "bool"),
+        ("(type-of 'a)", "symbol"),
+        ("(type-of :a)", "keyword"),
+        ("(type-of '())", "list"),
+        ("(type-of [1])", "vector"),
+        ("(type-of {1})", "set"),
+        ("(type-of #{1 2})", "map"),
+        ("(type-of (void))", "void"),
+        ("(type-of +)", "native"),
+        ("(type-of (lambda x x))", "lambda"),
+        ("(eq? (type-of 1) 'number)", "true"),
+        ("(number? 1)", "true"),
+        ("(number? \"1\")", "false"),
+        ("(integer? 1)", "true"),
+        ("(integer? 1.5)", "false"),
+        ("(bool? false)", "true"),
+        ("(string? \"s\")", "true"),
+        ("(list? '())", "true"),
+        ("(list? (cons 1 2))", "false"),
+        ("(pair? (cons 1 2))", "true"),
+        ("(pair? '())", "false"),
+        ("(vector? [])", "true"),
+        ("(set? {})", "true"),
+        ("(map? #{})", "true"),
+        ("(procedure? +)", "true"),
+        ("(procedure? (lambda x x))", "true"),
+        ("(procedure? 'car)", "false"),
+        ("(lambda? +)", "false"),
+        ("(lambda? (lambda x x))", "true"),
+    ];
+    for (source, expected) in cases {
+        let value = eval_program(&[source], &mut env);
+        assert_eq!(value.to_string(), expected, "{}", source);
+    }
+}
+
+#[test]
+fn test_lexical_closures() {
+
+    let mut env = default_env();
+
+    let value = eval_program(&[
+        "(fn adder n (lambda x (+ x n)))",
+        "(define add2 (adder 2))",
+        "(define n 100)",
+        "(add2 1)",
+    ], &mut env);
+    assert_eq!(value.to_string(), "3");
+}
+
+#[test]
+fn test_rest_params_and_begin() {
+
+    let mut env = default_env();
+
+    let value = eval_program(&[
+        "(fn tagged tag &rest items (cons tag items))",
+        "(tagged :a 1 2)",
+    ], &mut env);
+    assert_eq!(value.to_string(), "(:a 1 2)");
+
+    let value = eval_program(&["(tagged :a)"], &mut env);
+    assert_eq!(value.to_string(), "(:a)");
+
+    let value = eval_program(&["(begin (define b 1) (+ b 1))"], &mut env);
+    assert_eq!(value.to_string(), "2");
+
+    let value = eval_program(&["(apply + 1 '(2 3))"], &mut env);
+    assert_eq!(value.to_string(), "6");
+
+    let value = eval_program(&["(list (+) (*) (apply + '()) (apply * '()))"], &mut env);
+    assert_eq!(value.to_string(), "(0 1 0 1)");
+}
+
+#[test]
+fn test_fixed_arity() {
+
+    let mut env = default_env();
+
+    eval_program(&["(fn f a b (+ a b))", "(define b 10)", "(fn g a (+ a 1))"], &mut env);
+
+    for source in ["(f 1)", "(g 1 2 3)"] {
+        let tokens = tokenize(source.to_string());
+        let (exp, _) = parse(&tokens[..]).unwrap();
+        assert!(matches!(eval(exp, &mut env), Err(EvalError::InvalidNumberOfArguments())), "{}", source);
+    }
+}
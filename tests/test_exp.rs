@@ -64,7 +64,7 @@ fn test_parse_symbol_token() {
     
     for (token, value) in symbol_tokens {
         match parse_token(&token) {
-            LispExp::Symbol(symbol) => assert_eq!(symbol, value),
+            LispExp::Symbol(symbol) => assert_eq!(symbol.name(), value),
             _ => assert!(false)
         }
     }
@@ -82,7 +82,8 @@ fn test_parse_expression() {
 
     test_parse("(* (+ 4 1) 2)", "(* (+ 4 1) 2)");
 
-    test_parse("'(1 2 3 4)", "(list 1 2 3 4)");
+    test_parse("'(1 2 3 4)", "(quote (1 2 3 4))");
+    test_parse("'(a (b c))", "(quote (a (b c)))");
 
     test_parse("{1 2 3 4}", "(set 1 2 3 4)");
 
@@ -107,3 +108,56 @@ fn test_parse_exceprions() {
 
     test_parse_exception("'{+ 3 4}", ParseError::UnexpectedToken("{".to_string()));
 }
+
+#[test]
+fn test_string_literals() {
+
+    assert_eq!(tokenize("(print \"hello (world)\")".to_string()),
+        ["(", "print", "\"hello (world)\"", ")"]);
+
+    match parse_token("\"a \\\"quoted\\\" \\\\ line\\n\"") {
+        LispExp::Str(s) => assert_eq!(s, "a \"quoted\" \\ line\n"),
+        _ => panic!()
+    }
+
+    test_parse("(define s \"x y\")", "(define s \"x y\")");
+
+    test_parse_exception("\"unterminated", ParseError::UnterminatedString());
+}
+
+#[test]
+fn test_map_literal() {
+
+    assert_eq!(tokenize("#{1 2}".to_string()), ["#{", "1", "2", "}"]);
+
+    test_parse("#{1 2 3 {4}}", "(hash-map 1 2 3 (set 4))");
+
+    test_parse_exception("#{1 2", ParseError::MissingToken);
+}
+
+#[test]
+fn test_vector_literal() {
+
+    assert_eq!(tokenize("[1 [2]]".to_string()), ["[", "1", "[", "2", "]", "]"]);
+
+    test_parse("[1 (+ 1 1) [3]]", "(vector 1 (+ 1 1) (vector 3))");
+
+    test_parse_exception("[1 2", ParseError::MissingToken);
+    test_parse_exception("]", ParseError::UnexpectedToken("]".to_string()));
+}
+
+#[test]
+fn test_quote_symbol() {
+
+    assert_eq!(tokenize("(list 'a :b)".to_string()), ["(", "list", "'a", ":b", ")"]);
+
+    test_parse("(list 'a :b)", "(list (quote a) :b)");
+    test_parse("'\"s\"", "(quote \"s\")");
+}
+
+#[test]
+fn test_comments() {
+
+    assert_eq!(tokenize("(+ 1 ; one\n 2) ; done".to_string()), ["(", "+", "1", "2", ")"]);
+    assert_eq!(tokenize("(print \"a;b\")".to_string()), ["(", "print", "\"a;b\"", ")"]);
+}
@@ -0,0 +1,73 @@
+use rlisp::interpreter::Interpreter;
+use rlisp::utils::exceptions::{EvalError, LispError};
+
+use std::fs;
+use std::path::PathBuf;
+
+fn fs_root(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rlisp_test_{}", name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("data")).unwrap();
+    fs::write(dir.join("data/input.txt"), "1\n2\n3\n").unwrap();
+    dir
+}
+
+fn is_denied<T>(result: Result<T, LispError>) -> bool {
+    matches!(result, Err(LispError::Eval(EvalError::PermissionDenied(_))))
+}
+
+#[test]
+fn test_disabled_by_default() {
+
+    let dir = fs_root("fs_disabled");
+    let mut interp = Interpreter::new();
+
+    assert!(is_denied(interp.eval_str(&format!("(read-file \"{}\")", dir.join("data/input.txt").display()))));
+    assert!(is_denied(interp.eval_str("(file-exists? \"data\")")));
+}
+
+#[test]
+fn test_read_and_write() {
+
+    let dir = fs_root("fs_read_write");
+    let mut interp = Interpreter::new();
+    interp.allow_fs(&dir).unwrap();
+
+    assert!(is_denied(interp.eval_str("(write-file \"out/../result.txt\" \"x\")")));
+
+    let value = interp.eval_str("
+        (write-file \"result.txt\" \"first\n\")
+        (append-file \"result.txt\" \"second\n\")
+        (append-file \"new.txt\" \"created\")
+        (list (read-file \"result.txt\") (read-lines \"data/input.txt\") (file-exists? \"new.txt\"))
+    ").unwrap();
+    assert_eq!(value.to_string(), "(\"first\\nsecond\\n\" (\"1\" \"2\" \"3\") true)");
+    assert_eq!(fs::read_to_string(dir.join("new.txt")).unwrap(), "created");
+
+    let value = interp.eval_str("(list (list-dir \".\") (list-dir \"data\") (file-exists? \"missing/file\"))").unwrap();
+    assert_eq!(value.to_string(), "((\"data\" \"new.txt\" \"result.txt\") (\"input.txt\") false)");
+
+    assert!(matches!(interp.eval_str("(read-file \"missing.txt\")"),
+        Err(LispError::Eval(EvalError::IoError(_)))));
+    assert!(matches!(interp.eval_str("(write-file \"x.txt\" 1)"),
+        Err(LispError::Eval(EvalError::InvalidAgrumentType()))));
+}
+
+#[test]
+fn test_outside_root() {
+
+    let dir = fs_root("fs_outside");
+    let mut interp = Interpreter::new();
+    interp.allow_fs(dir.join("data")).unwrap();
+
+    assert!(is_denied(interp.eval_str("(read-file \"../data/input.txt\")")));
+    assert!(is_denied(interp.eval_str(&format!("(read-file \"{}\")", std::env::temp_dir().display()))));
+    assert_eq!(interp.eval_str("(read-file \"input.txt\")").unwrap().to_string(), "\"1\\n2\\n3\\n\"");
+
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(&dir, dir.join("data/escape")).unwrap();
+        assert!(is_denied(interp.eval_str("(list-dir \"escape\")")));
+        assert!(is_denied(interp.eval_str("(write-file \"escape/x.txt\" \"x\")")));
+    }
+}
@@ -0,0 +1,91 @@
+#![allow(unused)]
+
+use rlisp::eval::LispEval;
+use rlisp::native::Arity;
+use rlisp::interpreter::Interpreter;
+use rlisp::utils::exceptions::{EvalError, ParseError, LispError};
+
+use std::io::Write;
+
+#[test]
+fn test_eval_str() {
+
+    let mut interp = Interpreter::new();
+
+    let value = interp.eval_str("
+        (fn plus a b (+ a b))
+        (define x (plus 3 4))
+        (* x 2)
+    ").unwrap();
+
+    assert_eq!(value.to_string(), "14");
+
+    match interp.eval_str("(+ 1 2") {
+        Err(LispError::Parse(e)) => assert_eq!(e, ParseError::MissingToken),
+        _ => panic!()
+    }
+}
+
+#[test]
+fn test_eval_file() {
+
+    let path = std::env::temp_dir().join("rlisp_test_eval_file.rl");
+    let mut file = std::fs::File::create(&path).unwrap();
+    writeln!(file, "(define a {{1 2 3}})").unwrap();
+    writeln!(file, "(define b {{3 4 5}})").unwrap();
+    writeln!(file, "(inter a b)").unwrap();
+
+    let mut interp = Interpreter::new();
+    let value = interp.eval_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(value.to_string(), "{3}");
+
+    assert!(matches!(interp.eval_file(&path), Err(LispError::Io(_))));
+}
+
+#[test]
+fn test_globals_and_call() {
+
+    let mut interp = Interpreter::new();
+
+    interp.set_global("limit", LispEval::Number(10.0));
+    interp.define_native("clamp", Arity::Exact(1), "Clamps a number to the limit.",
+        |interp, args| {
+            match (&args[0], interp.get_global("limit")) {
+                (LispEval::Number(n), Some(LispEval::Number(limit))) => {
+                    Ok(LispEval::Number(n.min(limit)))
+                },
+                _ => Err(EvalError::InvalidAgrumentType())
+            }
+        }
+    );
+
+    interp.eval_str("(define y (clamp 42))").unwrap();
+    assert_eq!(interp.get_global("y").unwrap().to_string(), "10");
+
+    interp.eval_str("(fn plus a b (+ a b))").unwrap();
+    let value: LispEval = interp.call("plus", &[LispEval::Number(3.0), LispEval::Number(4.0)]).unwrap();
+    assert_eq!(value.to_string(), "7");
+
+    assert!(matches!(interp.call::<_, LispEval>("missing", ()), Err(EvalError::UnknownSymbol(_))));
+}
+
+#[test]
+fn test_error_messages() {
+
+    let mut interp = Interpreter::new();
+
+    let cases = [
+        ("(+ 1 2", "syntax error: unexpected end of input"),
+        ("unknown", "unknown symbol unknown"),
+        ("(car 1 2)", "wrong number of arguments to car: expected 1, got 2"),
+        ("(nth '(1) 3)", "index 3 out of range"),
+    ];
+    for (source, expected) in cases {
+        match interp.eval_str(source) {
+            Err(e) => assert_eq!(e.to_string(), expected, "{}", source),
+            Ok(_) => panic!("{}", source)
+        }
+    }
+}
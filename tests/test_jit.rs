@@ -0,0 +1,105 @@
+#![cfg(feature = "jit")]
+
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use rlisp::interpreter::Interpreter;
+use rlisp::limits::Limits;
+use rlisp::utils::exceptions::{EvalError, LispError};
+
+// Evaluates the program, with native code or with the tree-walker only. Any
+// limit keeps procedures from running as native code.
+fn run(native: bool, source: &str) -> String {
+    let mut interp = Interpreter::new();
+    if !native {
+        interp.set_limits(Limits { fuel: Some(u64::MAX), ..Limits::default() });
+    }
+    match interp.eval_str(source) {
+        Ok(value) => value.to_string(),
+        Err(e) => format!("{:?}", e),
+    }
+}
+
+fn assert_same(source: &str, expected: &str) {
+    assert_eq!(run(false, source), expected, "tree: {}", source);
+    assert_eq!(run(true, source), expected, "native: {}", source);
+}
+
+#[test]
+fn test_numeric_procedures() {
+
+    assert_same("
+        (fn fib n (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))
+        (map fib (range 15))
+    ", "(0 1 1 2 3 5 8 13 21 34 55 89 144 233 377)");
+
+    assert_same("(fn f x y (% (* x y) 7)) (list (f 5 3) (f 5.5 2) (f -9 1) (f 1 0))", "(1 4 -2 0)");
+    assert_same("
+        (fn neg x (- x))
+        (fn plus-six x (+ x 1 2 3))
+        (fn over-zero x (/ x 0))
+        (fn sub x (- 0 x 1))
+        (list (neg 4) (plus-six 4) (over-zero 4) (sub 4))
+    ", "(4 10 inf -5)");
+    assert_same("(fn f x (if (if (> x 0) (< x 10) false) 1 2)) (list (f 5) (f 50) (f -1))", "(1 2 2)");
+
+    // Comparisons of several numbers compare the last two
+    assert_same("(fn f x (if (< 3 x 2) 1 0)) (list (f 1) (f 3))", "(1 0)");
+    assert_same("(fn f x (if (= x x) 1 0)) (f (/ 0 0))", "0");
+
+    // Calls which are not numbers only are evaluated by the tree-walker
+    assert_same("(fn f x (+ x 1)) (f true)", "1");
+    assert_same("(fn f x (+ x 1)) (f)", "Eval(InvalidNumberOfArguments)");
+    assert_same("(fn f x (+ x 1)) (f 1 2)", "Eval(InvalidNumberOfArguments)");
+    assert_same("(fn f x (< x 1)) (f 0)", "true");
+    assert_same("(fn f x (+ x pi)) (f 1)", "4.141592653589793");
+}
+
+#[test]
+fn test_native_calls() {
+
+    let mut interp = Interpreter::new();
+    interp.eval_str("(fn fib n (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))").unwrap();
+    interp.reset_usage();
+
+    // Native code evaluates no expressions
+    assert_eq!(interp.eval_str("(fib 20)").unwrap().to_string(), "6765");
+    assert!(interp.usage().fuel < 10);
+}
+
+#[test]
+fn test_rebinding() {
+
+    // Redefined builtins and procedures are used once redefined
+    assert_same("
+        (fn f x (+ x 1))
+        (define a (f 1))
+        (define + *)
+        (list a (f 5))
+    ", "(2 5)");
+    assert_same("
+        (fn count n (if (= n 0) 0 (+ 1 (count (- n 1)))))
+        (define old count)
+        (fn count n 100)
+        (old 5)
+    ", "101");
+}
+
+#[test]
+fn test_interrupt() {
+
+    let mut interp = Interpreter::new();
+    interp.eval_str("(fn fib n (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))").unwrap();
+
+    let interrupt = interp.interrupt_handle();
+    let stopper = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        interrupt.store(true, Ordering::Relaxed);
+    });
+    let result = interp.eval_str("(fib 60)");
+    stopper.join().unwrap();
+
+    assert!(matches!(result, Err(LispError::Eval(EvalError::Interrupted))));
+    assert_eq!(interp.eval_str("(fib 20)").unwrap().to_string(), "6765");
+}
@@ -0,0 +1,150 @@
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use rlisp::eval::LispEval;
+use rlisp::interpreter::Interpreter;
+use rlisp::limits::{Limits, Resource};
+use rlisp::utils::exceptions::{EvalError, LispError};
+
+fn exhausted<T>(result: Result<T, LispError>) -> Option<Resource> {
+    match result {
+        Err(LispError::Eval(EvalError::ResourceExhausted(resource))) => Some(resource),
+        _ => None
+    }
+}
+
+#[test]
+fn test_fuel() {
+
+    let mut interp = Interpreter::new();
+    interp.set_limits(Limits { fuel: Some(200), ..Limits::default() });
+
+    interp.eval_str("(fn spin x (spin x))").unwrap();
+    assert_eq!(exhausted(interp.eval_str("(spin 1)")), Some(Resource::Fuel));
+
+    interp.set_limits(Limits { fuel: Some(100_000), ..Limits::default() });
+    assert_eq!(exhausted(interp.eval_str("(while true 1)")), Some(Resource::Fuel));
+
+    // The interpreter is still usable once the usage is reset
+    interp.reset_usage();
+    assert_eq!(interp.usage().fuel, 0);
+    assert_eq!(interp.eval_str("(+ 1 2)").unwrap().to_string(), "3");
+    assert!(interp.usage().fuel > 0);
+}
+
+#[test]
+fn test_depth() {
+
+    let mut interp = Interpreter::new();
+    interp.set_limits(Limits { max_depth: Some(50), ..Limits::default() });
+
+    interp.eval_str("(fn down n (if (= n 0) 0 (+ 1 (down (- n 1)))))").unwrap();
+    assert_eq!(interp.eval_str("(down 10)").unwrap().to_string(), "10");
+    assert_eq!(exhausted(interp.eval_str("(down 1000000)")), Some(Resource::Depth));
+
+    // Calls returning with an error leave the depth as it was
+    assert_eq!(interp.usage().depth, 0);
+    assert_eq!(interp.eval_str("(down 20)").unwrap().to_string(), "20");
+
+    // Tail calls through builtins count as well
+    interp.eval_str("(fn again n (apply again (list n)))").unwrap();
+    assert_eq!(exhausted(interp.eval_str("(again 1)")), Some(Resource::Depth));
+}
+
+#[test]
+fn test_memory() {
+
+    let mut interp = Interpreter::new();
+    interp.set_limits(Limits { max_memory: Some(100_000), ..Limits::default() });
+
+    assert_eq!(interp.eval_str("(length (range 100))").unwrap().to_string(), "100");
+    assert!(interp.usage().memory > 0);
+
+    assert_eq!(exhausted(interp.eval_str("(range 1000000)")), Some(Resource::Memory));
+
+    interp.reset_usage();
+    interp.eval_str("(define v (vector))").unwrap();
+    assert_eq!(exhausted(interp.eval_str("(while true (vector-push! v 1))")), Some(Resource::Memory));
+
+    interp.reset_usage();
+    interp.eval_str("(define l (list 1))").unwrap();
+    assert_eq!(exhausted(interp.eval_str("(while true (define l (append l l)))")), Some(Resource::Memory));
+
+    // New symbols are never freed and count as well
+    interp.reset_usage();
+    assert_eq!(exhausted(interp.eval_str("(while true (gensym))")), Some(Resource::Memory));
+    interp.reset_usage();
+    interp.eval_str("(dotimes (i 100) (string->symbol \"list\"))").unwrap();
+    assert_eq!(interp.usage().memory, 0);
+
+    // Values stored out of range are not counted
+    interp.eval_str("(define w (vector 1))").unwrap();
+    interp.reset_usage();
+    assert!(matches!(interp.eval_str("(vector-set! w 3 \"value\")"),
+        Err(LispError::Eval(EvalError::IndexOutOfRange(3)))));
+    assert_eq!(interp.usage().memory, 0);
+}
+
+#[test]
+fn test_builtin_sizes() {
+
+    let mut interp = Interpreter::new();
+    interp.eval_str("(define l (range 10000))").unwrap();
+    interp.eval_str("(define v (list->vector l))").unwrap();
+    interp.eval_str("(define m (hash-map 1 2 3 4))").unwrap();
+
+    // Builtins check what they build before building it
+    let sources = [
+        "(range 1e15)", "(append l l)", "(reverse l)", "(take l 5000)", "(zip l l)",
+        "(list->vector l)", "(vector->list v)", "(vector-slice v 0)",
+    ];
+    interp.set_limits(Limits { fuel: Some(1000), ..Limits::default() });
+    for source in sources {
+        interp.reset_usage();
+        assert_eq!(exhausted(interp.eval_str(source)), Some(Resource::Fuel), "{}", source);
+    }
+    interp.set_limits(Limits { max_memory: Some(10_000), ..Limits::default() });
+    for source in sources {
+        interp.reset_usage();
+        assert_eq!(exhausted(interp.eval_str(source)), Some(Resource::Memory), "{}", source);
+        assert_eq!(interp.usage().memory, 0, "{}", source);
+    }
+
+    // What they build is counted once
+    interp.set_limits(Limits { max_memory: Some(1_000_000), ..Limits::default() });
+    interp.eval_str("(vector-slice v 0)").unwrap();
+    assert_eq!(interp.usage().memory, 10000 * std::mem::size_of::<LispEval>());
+    assert_eq!(interp.eval_str("(length (keys m))").unwrap().to_string(), "2");
+}
+
+#[test]
+fn test_unlimited_by_default() {
+
+    let mut interp = Interpreter::new();
+    assert_eq!(interp.limits(), Limits::default());
+
+    let value = interp.eval_str("(length (range 100000))").unwrap();
+    assert_eq!(value.to_string(), "100000");
+    assert_eq!(interp.usage().memory, 0);
+}
+
+#[test]
+fn test_interrupt() {
+
+    let mut interp = Interpreter::new();
+    let interrupt = interp.interrupt_handle();
+
+    interp.eval_str("(define x 1)").unwrap();
+    let stopper = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        interrupt.store(true, Ordering::Relaxed);
+    });
+    let result = interp.eval_str("(while true (define x (+ x 1)))");
+    stopper.join().unwrap();
+    assert!(matches!(result, Err(LispError::Eval(EvalError::Interrupted))));
+
+    // The flag is cleared and the environment kept
+    assert!(!interp.interrupt_handle().load(Ordering::Relaxed));
+    assert_eq!(interp.eval_str("(> x 1)").unwrap().to_string(), "true");
+}
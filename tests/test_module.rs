@@ -0,0 +1,176 @@
+use rlisp::interpreter::Interpreter;
+use rlisp::utils::exceptions::{EvalError, LispError};
+
+use std::fs;
+use std::path::PathBuf;
+
+// Creates a fresh directory with the given files
+fn module_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rlisp_test_{}", name));
+    let _ = fs::remove_dir_all(&dir);
+    for (file, source) in files {
+        let path = dir.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, source).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn test_load_relative() {
+
+    let dir = module_dir("load", &[
+        ("main.rl", "(load \"lib/helpers.rl\") (double 21)"),
+        ("lib/helpers.rl", "(load \"constants.rl\") (fn double x (* x two))"),
+        ("lib/constants.rl", "(define two 2)"),
+    ]);
+
+    let mut interp = Interpreter::new();
+    let value = interp.eval_file(dir.join("main.rl")).unwrap();
+    assert_eq!(value.to_string(), "42");
+}
+
+#[test]
+fn test_import_module() {
+
+    let dir = module_dir("import", &[
+        ("main.rl", "
+            (import geometry square)
+            (define scale 100)
+            (list (geometry/area 3) (square 2) geometry/unit)
+        "),
+        ("geometry.rl", "
+            (module geometry (export area square unit))
+            (define scale 2)
+            (define unit 1)
+            (fn square x (* x x))
+            (fn area x (* scale (square x)))
+            (fn hidden x x)
+        "),
+    ]);
+
+    let mut interp = Interpreter::new();
+    let value = interp.eval_file(dir.join("main.rl")).unwrap();
+    assert_eq!(value.to_string(), "(18 4 1)");
+
+    // Private definitions and unqualified names stay inside the module
+    assert!(interp.get_global("hidden").is_none());
+    assert!(interp.get_global("geometry/hidden").is_none());
+    assert!(interp.get_global("area").is_none());
+    assert_eq!(interp.get_global("scale").unwrap().to_string(), "100");
+}
+
+#[test]
+fn test_module_cache() {
+
+    let dir = module_dir("cache", &[
+        ("counter.rl", "
+            (module counter (export loads))
+            (define loads [])
+            (vector-push! loads 1)
+        "),
+    ]);
+
+    let mut interp = Interpreter::new();
+    interp.add_search_path(&dir);
+    let value = interp.eval_str("
+        (import counter)
+        (import counter)
+        (vector-length counter/loads)
+    ").unwrap();
+    assert_eq!(value.to_string(), "1");
+}
+
+#[test]
+fn test_search_path() {
+
+    let dir = module_dir("search", &[
+        ("first/strings.rl", "(module strings (export greeting)) (define greeting \"hi\")"),
+        ("second/strings.rl", "(module strings (export greeting)) (define greeting \"hello\")"),
+        ("second/numbers.rl", "(module numbers (export one)) (define one 1)"),
+    ]);
+
+    let mut interp = Interpreter::new();
+    interp.add_search_path(dir.join("first"));
+    interp.add_search_path(dir.join("second"));
+    let value = interp.eval_str("(import strings) (import numbers) (list strings/greeting numbers/one)").unwrap();
+    assert_eq!(value.to_string(), "(\"hi\" 1)");
+
+    assert!(matches!(interp.eval_str("(import missing)"),
+        Err(LispError::Eval(EvalError::ModuleNotFound(_)))));
+}
+
+#[test]
+fn test_cycles() {
+
+    let dir = module_dir("cycles", &[
+        ("self.rl", "(load \"self.rl\")"),
+        ("a.rl", "(module a (export x)) (import b) (define x 1)"),
+        ("b.rl", "(module b (export y)) (import a) (define y 2)"),
+    ]);
+
+    let mut interp = Interpreter::new();
+    assert!(matches!(interp.eval_file(dir.join("self.rl")),
+        Err(LispError::Eval(EvalError::LoadCycle(_)))));
+
+    interp.add_search_path(&dir);
+    assert!(matches!(interp.eval_str("(import a)"),
+        Err(LispError::Eval(EvalError::LoadCycle(_)))));
+}
+
+#[test]
+fn test_invalid_modules() {
+
+    let dir = module_dir("invalid", &[
+        ("undeclared.rl", "(define x 1)"),
+        ("misnamed.rl", "(module other (export x)) (define x 1)"),
+        ("unexported.rl", "(module unexported (export missing))"),
+    ]);
+
+    let mut interp = Interpreter::new();
+    interp.add_search_path(&dir);
+
+    assert!(matches!(interp.eval_str("(import undeclared)"),
+        Err(LispError::Eval(EvalError::InvalidModule(_)))));
+    assert!(matches!(interp.eval_str("(import misnamed)"),
+        Err(LispError::Eval(EvalError::InvalidModule(_)))));
+    assert!(matches!(interp.eval_str("(import unexported)"),
+        Err(LispError::Eval(EvalError::UnknownSymbol(_)))));
+    assert!(matches!(interp.eval_str("(module main)"),
+        Err(LispError::Eval(EvalError::InvalidModule(_)))));
+}
+
+#[test]
+fn test_file_access() {
+
+    let dir = module_dir("access", &[
+        ("outside.rl", "(define secret 1)"),
+        ("main/main.rl", "(load \"../outside.rl\")"),
+        ("main/import.rl", "(import ../outside)"),
+        ("main/syntax.rl", "(load \"broken.rl\")"),
+        ("main/broken.rl", "(define token secret-token"),
+    ]);
+
+    // Files are only read from the directory of the file being run, the
+    // search path and the file system root
+    let mut interp = Interpreter::new();
+    assert!(matches!(interp.eval_str("(load \"/etc/hostname\")"),
+        Err(LispError::Eval(EvalError::PermissionDenied(_)))));
+    let path = dir.join("outside.rl").display().to_string();
+    assert!(matches!(interp.eval_str(&format!("(load {:?})", path)),
+        Err(LispError::Eval(EvalError::PermissionDenied(_)))));
+    assert!(matches!(interp.eval_file(dir.join("main/main.rl")),
+        Err(LispError::Eval(EvalError::PermissionDenied(_)))));
+    assert!(matches!(interp.eval_file(dir.join("main/import.rl")),
+        Err(LispError::Eval(EvalError::PermissionDenied(_)))));
+
+    interp.allow_fs(&dir).unwrap();
+    interp.eval_file(dir.join("main/main.rl")).unwrap();
+    assert_eq!(interp.get_global("secret").unwrap().to_string(), "1");
+
+    // Syntax errors do not show the contents of the file
+    match interp.eval_file(dir.join("main/syntax.rl")) {
+        Err(LispError::Eval(EvalError::LoadError(message))) => assert!(!message.contains("secret-token")),
+        _ => panic!(),
+    }
+}
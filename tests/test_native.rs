@@ -0,0 +1,81 @@
+#![allow(unused)]
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use rlisp::exp::*;
+use rlisp::env::*;
+use rlisp::eval::*;
+use rlisp::native::*;
+use rlisp::interpreter::Interpreter;
+use rlisp::utils::exceptions::EvalError;
+
+fn eval_input(input: &str, env: &mut LispEnv) -> Result<LispEval, EvalError> {
+    let tokens = tokenize(input.to_string());
+    let (exp, _) = parse(&tokens[..]).unwrap();
+    eval(exp, env)
+}
+
+#[test]
+fn test_native_closure() {
+
+    let mut env = default_env();
+
+    let calls = Rc::new(Cell::new(0));
+    let counter = calls.clone();
+    env.add_native(NativeFn::new("tick", Arity::Exact(0), "Counts calls.",
+        move |_, _| {
+            counter.set(counter.get() + 1);
+            Ok(LispEval::Number(counter.get() as f64))
+        }
+    ));
+
+    eval_input("(tick)", &mut env).unwrap();
+    let value = eval_input("(tick)", &mut env).unwrap();
+
+    assert_eq!(value.to_string(), "2");
+    assert_eq!(calls.get(), 2);
+}
+
+#[test]
+fn test_native_arity() {
+
+    let mut env = default_env();
+
+    match eval_input("(cons 1 '() 2)", &mut env) {
+        Err(EvalError::ArityMismatch(name, arity, count)) => {
+            assert_eq!(name, "cons");
+            assert_eq!(arity, Arity::Exact(2));
+            assert_eq!(count, 3);
+        },
+        _ => panic!()
+    }
+
+    match eval_input("(head)", &mut env) {
+        Err(EvalError::ArityMismatch(name, _, _)) => assert_eq!(name, "head"),
+        _ => panic!()
+    }
+}
+
+#[test]
+fn test_native_calls_back() {
+
+    let mut env = default_env();
+
+    env.add_native(NativeFn::new("twice", Arity::Exact(2), "Applies a procedure twice.",
+        |interp, args| {
+            let once = interp.apply(&args[0], &args[1..])?;
+            interp.apply(&args[0], &[once])
+        }
+    ));
+
+    let value = eval_input("(twice (lambda x (* x 3)) 2)", &mut env).unwrap();
+    assert_eq!(value.to_string(), "18");
+
+    if let Some(LispEval::Func(func)) = env.get("map") {
+        assert_eq!(func.name, "map");
+        assert!(!func.doc.is_empty());
+    } else {
+        panic!();
+    }
+}
@@ -0,0 +1,79 @@
+use rlisp::interpreter::{Interpreter, Engine};
+
+// Evaluates the program with an engine, optimized or not, returning the
+// printed result or error
+fn run(engine: Engine, optimize: bool, source: &str) -> String {
+    let mut interp = Interpreter::new();
+    interp.set_engine(engine);
+    interp.set_optimize(optimize);
+    match interp.eval_str(source) {
+        Ok(value) => value.to_string(),
+        Err(e) => format!("{:?}", e),
+    }
+}
+
+fn assert_same(source: &str, expected: &str) {
+    for engine in [Engine::Tree, Engine::Vm] {
+        assert_eq!(run(engine, false, source), expected, "{:?}: {}", engine, source);
+        assert_eq!(run(engine, true, source), expected, "{:?} -O: {}", engine, source);
+    }
+}
+
+#[test]
+fn test_constants() {
+
+    assert_same("(define tau (* 2 pi)) tau", "6.283185307179586");
+    assert_same("(list (+ 1 2) (< 1 2) (not true) (eq? \"a\" \"a\") (/ 1 0))", "(3 true false true inf)");
+    assert_same("(if (> e 2) 'yes (undefined-procedure))", "yes");
+    assert_same("(if (number? 1) 1 2 3)", "Eval(InvalidNumberOfArguments)");
+    assert_same("(if 1 2 3)", "Eval(InvalidIfStatement)");
+    assert_same("(not 1 2)", "Eval(ArityMismatch(\"not\", Exact(1), 2))");
+
+    // Redefined and shadowed builtins are not folded
+    assert_same("(define pi 3) (* 2 pi)", "6");
+    assert_same("(fn add a b (- a b)) (define + add) (+ 3 1)", "2");
+    assert_same("(fn f + (+ 1 2)) (f -)", "-1");
+    assert_same("(fn g x (begin (define * +) (* 2 3))) (g 0)", "5");
+}
+
+#[test]
+fn test_inlining() {
+
+    assert_same("(fn sq x (* x x)) (fn area r (* pi (sq r))) (area 2)", "12.566370614359172");
+    assert_same("(fn sq x (* x x)) (define n 3) (sq n)", "9");
+
+    // Arguments with effects are evaluated once
+    assert_same("
+        (fn twice x (+ x x))
+        (define calls [])
+        (fn next x (begin (vector-push! calls x) x))
+        (list (twice (next 1)) (vector-length calls))
+    ", "(2 1)");
+
+    // Variables of the inlined body are not captured at the place of the call
+    assert_same("
+        (define n 10)
+        (fn plus-n x (+ x n))
+        (fn call n (plus-n 1))
+        (call 0)
+    ", "11");
+
+    // Recursive and redefined procedures are not inlined
+    assert_same("(fn fact n (if (= n 0) 1 (* n (fact (- n 1))))) (fact 5)", "120");
+    assert_same("(fn f x (+ x 1)) (define a (f 1)) (fn f x (+ x 2)) (list a (f 1))", "(2 3)");
+    assert_same("(fn ev? k (if (= k 0) true (od? (- k 1)))) (fn od? k (if (= k 0) false (ev? (- k 1)))) (ev? 10)", "true");
+}
+
+#[test]
+fn test_less_work() {
+
+    let source = "(fn sq x (* x x)) (sq (+ 1 2))";
+
+    let mut plain = Interpreter::new();
+    assert_eq!(plain.eval_str(source).unwrap().to_string(), "9");
+
+    let mut optimized = Interpreter::new();
+    optimized.set_optimize(true);
+    assert_eq!(optimized.eval_str(source).unwrap().to_string(), "9");
+    assert!(optimized.usage().fuel < plain.usage().fuel);
+}
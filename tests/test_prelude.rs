@@ -0,0 +1,96 @@
+use rlisp::interpreter::Interpreter;
+use rlisp::utils::exceptions::{EvalError, LispError};
+
+fn eval(source: &str) -> String {
+    Interpreter::new().eval_str(source).unwrap().to_string()
+}
+
+#[test]
+fn test_control_macros() {
+
+    assert_eq!(eval("(when (> 2 1) (define x 1) (+ x 1))"), "2");
+    assert_eq!(eval("(void? (when false (undefined)))"), "true");
+    assert_eq!(eval("(unless false 1 2)"), "2");
+
+    let source = "
+        (fn classify n
+          (cond ((< n 0) :negative)
+                ((= n 0) :zero)
+                (else :positive)))
+        (map classify '(-5 0 5))
+    ";
+    assert_eq!(eval(source), "(:negative :zero :positive)");
+    assert_eq!(eval("(void? (cond (false 1)))"), "true");
+
+    assert_eq!(eval("(let ((x 2) (y 3)) (define z (* x y)) (+ z 1))"), "7");
+    assert_eq!(eval("(let () 5)"), "5");
+    assert_eq!(eval("(fn f a (let ((b (* a 2))) (lambda c (+ a b c)))) (define g (f 1)) (g 10)"), "13");
+}
+
+#[test]
+fn test_math_helpers() {
+
+    let cases = [
+        ("(inc 1)", "2"),
+        ("(dec 1)", "0"),
+        ("(abs -3)", "3"),
+        ("(square 4)", "16"),
+        ("(cube 2)", "8"),
+        ("(list (zero? 0) (positive? -1) (negative? -1))", "(true false true)"),
+        ("(list (even? 4) (odd? 4) (odd? -3))", "(true false true)"),
+        ("(max 1 5 3)", "5"),
+        ("(min 4)", "4"),
+        ("(clamp 12 0 10)", "10"),
+        ("(gcd 12 18)", "6"),
+        ("(lcm 4 6)", "12"),
+        ("(expt 2 10)", "1024"),
+    ];
+    for (source, expected) in cases {
+        assert_eq!(eval(source), expected, "{}", source);
+    }
+}
+
+#[test]
+fn test_list_utilities() {
+
+    let cases = [
+        ("(identity 1)", "1"),
+        ("(define h (compose inc square)) (h 3)", "10"),
+        ("(define h (compose square +)) (h 1 2)", "9"),
+        ("(empty? '())", "true"),
+        ("(sum '(1 2 3))", "6"),
+        ("(product '())", "1"),
+        ("(maximum '(3 9 2))", "9"),
+        ("(minimum [3 9 2])", "2"),
+        ("(remove even? '(1 2 3 4))", "(1 3)"),
+        ("(partition even? '(1 2 3 4))", "((2 4) (1 3))"),
+        ("(take-while odd? '(1 3 4 5))", "(1 3)"),
+        ("(drop-while odd? '(1 3 4 5))", "(4 5)"),
+        ("(repeat :x 3)", "(:x :x :x)"),
+    ];
+    for (source, expected) in cases {
+        assert_eq!(eval(source), expected, "{}", source);
+    }
+}
+
+#[test]
+fn test_bare_interpreter() {
+
+    let mut interp = Interpreter::bare();
+    assert!(matches!(interp.eval_str("(inc 1)"),
+        Err(LispError::Eval(EvalError::NonDefineInThisScope))));
+    assert_eq!(interp.eval_str("(+ 1 1)").unwrap().to_string(), "2");
+}
+
+#[test]
+fn test_user_macros() {
+
+    let source = "
+        (defmacro swap-args f a b (list f b a))
+        (swap-args - 1 10)
+    ";
+    assert_eq!(eval(source), "9");
+
+    assert!(matches!(Interpreter::new().eval_str("(defmacro bad x +) (bad 1)"),
+        Err(LispError::Eval(EvalError::InvalidMacroExpansion(_)))));
+}
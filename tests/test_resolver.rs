@@ -0,0 +1,69 @@
+use rlisp::interpreter::Interpreter;
+use rlisp::utils::exceptions::{EvalError, LispError};
+
+fn unbound<T>(result: Result<T, LispError>) -> Option<String> {
+    match result {
+        Err(LispError::Eval(EvalError::UnknownSymbol(name))) => Some(name),
+        _ => None
+    }
+}
+
+#[test]
+fn test_locals() {
+
+    let mut interp = Interpreter::new();
+
+    interp.eval_str("(fn adder n (lambda x (+ x n)))").unwrap();
+    let value = interp.eval_str("(define add2 (adder 2)) (add2 40)").unwrap();
+    assert_eq!(value.to_string(), "42");
+
+    // Variables defined later in a procedure are looked up outside until then
+    let value = interp.eval_str("
+        (define n 1)
+        (fn later x (begin (define before n) (define n x) (list before n)))
+        (later 2)
+    ").unwrap();
+    assert_eq!(value.to_string(), "(1 2)");
+
+    // Variables defined by macro expansions are found by name
+    let value = interp.eval_str("(fn half x (begin (when (> x 1) (define h (/ x 2))) h)) (half 8)").unwrap();
+    assert_eq!(value.to_string(), "4");
+
+    let value = interp.eval_str("(fn total l (begin (define s 0) (dolist (x l s) (define s (+ s x))))) (total '(1 2 3))").unwrap();
+    assert_eq!(value.to_string(), "6");
+}
+
+#[test]
+fn test_unbound_variables() {
+
+    let mut interp = Interpreter::new();
+
+    // Nothing runs when a variable is never bound
+    assert_eq!(unbound(interp.eval_str("(define ran true) (+ missing 1)")), Some("missing".to_string()));
+    assert_eq!(unbound(interp.eval_str("ran")), Some("ran".to_string()));
+
+    assert_eq!(unbound(interp.eval_str("(fn f x (+ x y))")), Some("y".to_string()));
+    assert_eq!(unbound(interp.eval_str("(fn f x (lambda y (+ y z)))")), Some("z".to_string()));
+
+    // Procedures may be called before they are defined
+    interp.eval_str("(fn first x (second x))").unwrap();
+    interp.eval_str("(fn second x (* x 2))").unwrap();
+    assert_eq!(interp.eval_str("(first 21)").unwrap().to_string(), "42");
+
+    // Names defined anywhere in the program are bound
+    let value = interp.eval_str("(fn get-v x v) (define v 3) (get-v 0)").unwrap();
+    assert_eq!(value.to_string(), "3");
+    assert_eq!(interp.eval_str("(dotimes (i 3 i) i)").unwrap().to_string(), "3");
+    assert_eq!(interp.eval_str("'unbound").unwrap().to_string(), "unbound");
+
+    // Names quoted in macro bodies may be defined by their expansions
+    let value = interp.eval_str("
+        (defmacro make-counter x (list 'define 'counter x))
+        (defmacro reset unused '(define total 0))
+        (make-counter 5)
+        (reset 1)
+        (list counter total)
+    ").unwrap();
+    assert_eq!(value.to_string(), "(5 0)");
+    assert_eq!(interp.eval_str(":key").unwrap().to_string(), ":key");
+}
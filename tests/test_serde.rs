@@ -0,0 +1,101 @@
+#![cfg(feature = "serde")]
+#![allow(unused)]
+
+use rlisp::de::{from_str, from_value};
+use rlisp::ser::{to_string, to_value};
+use rlisp::eval::LispEval;
+use rlisp::utils::exceptions::SerdeError;
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum Mode {
+    Fast,
+    Retries(u32),
+    Window { from: f64, to: f64 },
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Config {
+    name: String,
+    port: u16,
+    ratio: f64,
+    hosts: Vec<String>,
+    limits: HashMap<String, u32>,
+    backup: Option<String>,
+    modes: Vec<Mode>,
+}
+
+#[test]
+fn test_deserialize_config() {
+
+    let config: Config = from_str("
+        (define base 8000)
+        (fn host n (+ n 1))
+        (list
+            (list \"name\" \"service\")
+            (list \"port\" (+ base 80))
+            (list \"ratio\" (/ 1 4))
+            (list \"hosts\" (list \"a\" \"b\"))
+            (list \"limits\" (list (list \"cpu\" 4)))
+            (list \"backup\" '())
+            (list \"modes\" (list \"Fast\"
+                                  (list (list \"Retries\" 3))
+                                  (list (list \"Window\" (list (list \"from\" 0) (list \"to\" 1.5)))))))
+    ").unwrap();
+
+    assert_eq!(config, Config {
+        name: "service".to_string(),
+        port: 8080,
+        ratio: 0.25,
+        hosts: vec!["a".to_string(), "b".to_string()],
+        limits: HashMap::from([("cpu".to_string(), 4)]),
+        backup: None,
+        modes: vec![Mode::Fast, Mode::Retries(3), Mode::Window { from: 0.0, to: 1.5 }],
+    });
+}
+
+#[test]
+fn test_deserialize_errors() {
+
+    assert!(matches!(from_str::<u16>("(+ 1"), Err(SerdeError::Lisp(_))));
+    assert!(matches!(from_str::<u16>("-1"), Err(SerdeError::Message(_))));
+    assert!(matches!(from_str::<Vec<f64>>("+"), Err(SerdeError::Message(_))));
+    assert_eq!(from_str::<Vec<f64>>("{2}").unwrap(), vec![2.0]);
+}
+
+#[test]
+fn test_serialize_round_trip() {
+
+    let config = Config {
+        name: "say \"hi\"".to_string(),
+        port: 22,
+        ratio: 0.5,
+        hosts: vec![],
+        limits: HashMap::from([("mem".to_string(), 512)]),
+        backup: Some("b".to_string()),
+        modes: vec![Mode::Retries(1), Mode::Window { from: 1.0, to: 2.0 }],
+    };
+
+    let source = to_string(&config).unwrap();
+    assert!(source.starts_with("#{"));
+    assert!(source.contains("\"name\" \"say \\\"hi\\\"\""));
+
+    let parsed: Config = from_str(&source).unwrap();
+    assert_eq!(parsed, config);
+
+    assert_eq!(to_value(&(1, true)).unwrap().to_string(), "(1 true)");
+}
+
+#[test]
+fn test_deserialize_map_literal() {
+
+    let limits: HashMap<String, u32> = from_str("
+        (define defaults #{\"cpu\" 1 \"mem\" 256})
+        (assoc defaults \"cpu\" 4)
+    ").unwrap();
+
+    assert_eq!(limits, HashMap::from([("cpu".to_string(), 4), ("mem".to_string(), 256)]));
+}
@@ -0,0 +1,41 @@
+use rlisp::exp::{LispExp, parse_token};
+use rlisp::symbol::Symbol;
+
+#[test]
+fn test_interning() {
+
+    let a = Symbol::intern("interned");
+    assert_eq!(a, Symbol::intern("interned"));
+    assert_ne!(a, Symbol::intern("other"));
+    assert_eq!(a.name(), "interned");
+    assert_eq!(a.to_string(), "interned");
+
+    assert!(Symbol::intern(":key").is_keyword());
+    assert!(!Symbol::intern(":").is_keyword());
+    assert!(!a.is_keyword());
+}
+
+#[test]
+fn test_gensym() {
+
+    let taken = Symbol::intern("gen1");
+    let fresh = Symbol::gensym("gen");
+    assert_ne!(fresh, taken);
+    assert!(fresh.name().starts_with("gen"));
+    assert_ne!(Symbol::gensym("gen"), fresh);
+}
+
+#[test]
+fn test_predefined() {
+
+    assert_eq!(Symbol::intern("define"), Symbol::DEFINE);
+    assert_eq!(Symbol::intern("&rest"), Symbol::REST);
+    assert_eq!(Symbol::HASH_MAP.name(), "hash-map");
+    assert!(!Symbol::QUOTE.is_keyword());
+
+    // Symbols are interned while parsing
+    match parse_token("lambda") {
+        LispExp::Symbol(symbol) => assert_eq!(symbol, Symbol::LAMBDA),
+        _ => panic!()
+    }
+}
@@ -0,0 +1,112 @@
+use rlisp::interpreter::{Interpreter, Engine};
+
+// Evaluates the program with an engine, returning the printed result or error
+fn run(engine: Engine, source: &str) -> String {
+    let mut interp = Interpreter::new();
+    interp.set_engine(engine);
+    match interp.eval_str(source) {
+        Ok(value) => value.to_string(),
+        Err(e) => format!("{:?}", e),
+    }
+}
+
+fn assert_same(source: &str, expected: &str) {
+    assert_eq!(run(Engine::Tree, source), expected, "tree: {}", source);
+    assert_eq!(run(Engine::Vm, source), expected, "vm: {}", source);
+}
+
+#[test]
+fn test_procedures() {
+
+    assert_same("
+        (fn fib n (if (< n 2) 1 (+ (fib (- n 1)) (fib (- n 2)))))
+        (map fib (range 10))
+    ", "(1 1 2 3 5 8 13 21 34 55)");
+
+    assert_same("
+        (fn counter start (begin
+            (define n start)
+            (lambda step (+ n step))))
+        (define c (counter 10))
+        (c 5)
+    ", "15");
+
+    // Defining a variable in a procedure shadows the outer one from then on
+    assert_same("
+        (fn shadow n (begin
+            (define f (lambda x (begin (define y (* n 2)) (define n 0) (+ y n))))
+            (f 0)))
+        (shadow 21)
+    ", "42");
+    assert_same("(define x 5) (fn f n (begin (define x (+ x n)) x)) (list (f 1) x)", "(6 5)");
+    assert_same("
+        (define total 0)
+        (fn sum-to n (begin (dotimes (i n) (define total (+ total i))) total))
+        (list (sum-to 4) total)
+    ", "(6 0)");
+    assert_same("(fn f n (begin (define y (+ y n)) y)) (f 1)", "Eval(UnknownSymbol(\"y\"))");
+    assert_same("
+        (fn shadow n (begin
+            (define f (lambda x (begin (define n (* x 2)) n)))
+            (list (f 21) n)))
+        (shadow 1)
+    ", "(42 1)");
+
+    // Procedures defined in a body can call each other
+    assert_same("
+        (fn parity n (begin
+            (fn ev? k (if (= k 0) true (od? (- k 1))))
+            (fn od? k (if (= k 0) false (ev? (- k 1))))
+            (list (ev? n) (od? n))))
+        (parity 7)
+    ", "(false true)");
+
+    assert_same("(fn f a &rest more (list a more)) (f 1 2 3)", "(1 (2 3))");
+    assert_same("(apply (lambda x (* x 2)) (list 21))", "42");
+    assert_same("(when (> 2 1) (define x 1) (+ x 1))", "2");
+    assert_same("(let ((x 2) (y 3)) (* x y))", "6");
+    assert_same("(fn f x '(x (+ x 1))) (f 1)", "(x (+ x 1))");
+}
+
+#[test]
+fn test_loops() {
+
+    assert_same("(define sum 0) (dotimes (i 5 sum) (define sum (+ sum i)))", "10");
+    assert_same("(dotimes (i 3 i) 0)", "3");
+    assert_same("(define n 0) (dolist (x (list 1 2 3) n) (define n (+ n x)))", "6");
+    assert_same("(define v [1 2 3]) (define s 0) (dolist (x v s) (define s (+ s x)))", "6");
+    assert_same("(do ((i 0 (+ i 1)) (acc 1 (* acc 2))) ((= i 10) acc))", "1024");
+    assert_same("(define i 0) (while (< i 10) (define i (+ i 1))) i", "10");
+
+    // Loop variables are bound while the loop runs only
+    assert_same("
+        (define i 10)
+        (fn show x (+ x i))
+        (define shown (list))
+        (dotimes (i 3) (define shown (cons (show 0) shown)))
+        (list shown i)
+    ", "((2 1 0) 10)");
+    assert_same("
+        (fn adders n (begin
+            (define i 100)
+            (define fs (list))
+            (dotimes (i n) (define fs (cons (lambda x (+ x i)) fs)))
+            fs))
+        (map (lambda f (f 0)) (adders 3))
+    ", "(100 100 100)");
+}
+
+#[test]
+fn test_errors() {
+
+    assert_same("(undefined 1)", "Eval(NonDefineInThisScope)");
+    assert_same("(+ x 1)", "Eval(UnknownSymbol(\"x\"))");
+    assert_same("(if 1 2 3)", "Eval(InvalidIfStatement)");
+    assert_same("(if false (if) 3)", "3");
+    assert_same("(while 1 2)", "Eval(InvalidLoopStatement)");
+    assert_same("(dotimes (i \"3\") i)", "Eval(InvalidAgrumentType)");
+    assert_same("(define f (lambda x x)) (f)", "Eval(InvalidNumberOfArguments)");
+    assert_same("(fn f a b (+ a b)) (define b 10) (f 1)", "Eval(InvalidNumberOfArguments)");
+    assert_same("(fn f a (+ a 1)) (f 1 2 3)", "Eval(InvalidNumberOfArguments)");
+    assert_same("(1 2)", "Eval(InvalidListExpression)");
+}